@@ -0,0 +1,574 @@
+use crate::original::{self, Original};
+use crate::variant::{self, CountStrategy, CounterStorage, Number, Variant};
+use crate::wide::WideVariant;
+use crate::{Alphabet, Counter, Counts, Error, Process, Result};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// The counting engines that can be selected by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// `Original<StdNumeric>`: the reference implementation.
+    Original,
+    /// `Original<AlphabetDigit>`
+    OriginalHex,
+    /// `Variant<EarlyCount, HashMapCounter>`
+    Variant1,
+    /// `Variant<LateCount, HashMapCounter>`
+    Variant2,
+    /// `Variant<EarlyCount, VecCounter>`
+    Variant3,
+    /// `Variant<LateCount, VecCounter>`
+    Variant4,
+    /// `Variant<LateCount, CompactVecCounter<u64, u32>>`: `variant-4` with
+    /// half the memory, up to 2^32 - 2 occurrences per string.
+    Variant4U32,
+    /// `Variant<LateCount, CompactVecCounter<u64, u16>>`: `variant-4` with a
+    /// quarter of the memory, up to 65534 occurrences per string.
+    Variant4U16,
+    /// One of the variants, chosen by [`Algorithm::choose`] from estimates
+    /// of their memory use.
+    Auto,
+}
+
+impl Algorithm {
+    pub const ALL: &'static [Algorithm] = &[
+        Algorithm::Original,
+        Algorithm::OriginalHex,
+        Algorithm::Variant1,
+        Algorithm::Variant2,
+        Algorithm::Variant3,
+        Algorithm::Variant4,
+        Algorithm::Variant4U32,
+        Algorithm::Variant4U16,
+        Algorithm::Auto,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Original => "original",
+            Algorithm::OriginalHex => "original-hex",
+            Algorithm::Variant1 => "variant-1",
+            Algorithm::Variant2 => "variant-2",
+            Algorithm::Variant3 => "variant-3",
+            Algorithm::Variant4 => "variant-4",
+            Algorithm::Variant4U32 => "variant-4-u32",
+            Algorithm::Variant4U16 => "variant-4-u16",
+            Algorithm::Auto => "auto",
+        }
+    }
+
+    /// The size of a table entry if the counts are kept in tables with an
+    /// entry for every possible substring, rather than in hash maps.
+    fn table_slot_bytes(self) -> Option<usize> {
+        match self {
+            Algorithm::Variant3 | Algorithm::Variant4 => Some(std::mem::size_of::<Counter>()),
+            Algorithm::Variant4U32 => Some(std::mem::size_of::<u32>()),
+            Algorithm::Variant4U16 => Some(std::mem::size_of::<u16>()),
+            _ => None,
+        }
+    }
+
+    /// An estimate of the peak memory use of the counts, in bytes, for an
+    /// input of `input_size` bytes or of unknown size. `None` for the
+    /// original algorithms, which are not estimated, and for `auto`.
+    ///
+    /// The overflow maps of compact tables are left out, they only hold
+    /// strings that occur extremely often.
+    pub fn memory_estimate(
+        self,
+        digits: usize,
+        alphabet: &Alphabet,
+        input_size: Option<u64>,
+    ) -> Option<u64> {
+        let packing = Packing::for_digits(digits, alphabet);
+        let radix = alphabet.radix() as u64;
+        let mut bytes = 0u64;
+        // NB: Every width from 0 to `digits` has its own table or map.
+        let mut possible = Some(1u64);
+        for width in 0..=digits as u64 {
+            let entries = match (self, self.table_slot_bytes(), packing) {
+                (_, Some(slot), Packing::U64 | Packing::U128) => {
+                    possible?.saturating_mul(slot as u64)
+                }
+                (Algorithm::Variant1 | Algorithm::Variant2, _, _) | (_, Some(_), Packing::Wide) => {
+                    let windows =
+                        input_size.map_or(u64::MAX, |size| (size + 1).saturating_sub(width));
+                    possible
+                        .unwrap_or(u64::MAX)
+                        .min(windows)
+                        .saturating_mul(packing.hash_entry_bytes(digits))
+                }
+                _ => return None,
+            };
+            bytes = bytes.saturating_add(entries);
+            possible = possible.and_then(|p| p.checked_mul(radix));
+        }
+        Some(bytes)
+    }
+
+    /// Picks the fastest variant whose counts are expected to fit into
+    /// `available_memory`, or a default budget if that is unknown.
+    ///
+    /// `copies` is the number of counts kept at the same time, like one per
+    /// thread.
+    pub fn choose(
+        digits: usize,
+        alphabet: &Alphabet,
+        input_size: Option<u64>,
+        available_memory: Option<u64>,
+        copies: usize,
+    ) -> Choice {
+        let budget = available_memory.map_or(DEFAULT_MEMORY_BUDGET, |m| m / 4 * 3);
+        let copies = copies.max(1) as u64;
+        let estimate = |algorithm: Algorithm| {
+            algorithm
+                .memory_estimate(digits, alphabet, input_size)
+                .map(|bytes| bytes.saturating_mul(copies))
+        };
+        let tables = Packing::for_digits(digits, alphabet) != Packing::Wide;
+        let table_bytes = estimate(Algorithm::Variant4).filter(|_| tables);
+        let compact_table_bytes = estimate(Algorithm::Variant4U32).filter(|_| tables);
+        let hash_bytes = estimate(Algorithm::Variant2).unwrap_or(u64::MAX);
+        // NB: Tables are much quicker than hash maps, but clearing huge ones
+        // for a few entries is not.
+        let fits = |bytes: Option<u64>| {
+            bytes.is_some_and(|bytes| bytes <= budget && bytes / TABLE_HASH_RATIO <= hash_bytes)
+        };
+        let algorithm = if fits(table_bytes) {
+            Algorithm::Variant4
+        } else if fits(compact_table_bytes) {
+            Algorithm::Variant4U32
+        } else if hash_bytes <= budget {
+            Algorithm::Variant2
+        } else {
+            // NB: Nothing fits, take the smallest.
+            match compact_table_bytes {
+                Some(compact) if compact < hash_bytes => Algorithm::Variant4U32,
+                _ => Algorithm::Variant2,
+            }
+        };
+        Choice {
+            algorithm,
+            table_bytes,
+            compact_table_bytes,
+            hash_bytes,
+            budget,
+            available_memory,
+        }
+    }
+
+    /// Checks that this algorithm counts the digits of `alphabet`. The
+    /// `original` one only knows decimal digits.
+    pub fn check_alphabet(self, alphabet: &Alphabet) -> Result<()> {
+        if self == Algorithm::Original && alphabet.symbols() != Alphabet::decimal().symbols() {
+            return Err(Error::InvalidArgs(
+                "original only counts decimal digits, use original-hex for other alphabets"
+                    .to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that this algorithm can count substrings of up to `digits`
+    /// digits of `alphabet`.
+    pub fn check_width(self, digits: usize, alphabet: &Alphabet) -> Result<()> {
+        let unsupported = |reason: String| Err(Error::UnsupportedWidth { digits, reason });
+        if digits == 0 {
+            return unsupported("at least one digit is needed".to_owned());
+        }
+        if let Some(slot) = self
+            .table_slot_bytes()
+            .filter(|_| Packing::for_digits(digits, alphabet) != Packing::Wide)
+        {
+            let table_bytes = u32::try_from(digits)
+                .ok()
+                .and_then(|d| alphabet.radix().checked_pow(d))
+                .and_then(|len| len.checked_mul(slot))
+                .filter(|&bytes| bytes <= isize::MAX as usize);
+            if table_bytes.is_none() {
+                return unsupported(format!(
+                    "the count tables of {} would not fit into the address space, \
+                     use variant-1 or variant-2",
+                    self
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The memory [`Algorithm::choose`] may use if the available memory is
+/// unknown.
+const DEFAULT_MEMORY_BUDGET: u64 = 4 << 30;
+
+/// How many times larger than the hash maps the tables may be and still be
+/// preferred.
+const TABLE_HASH_RATIO: u64 = 4;
+
+/// The algorithm picked by [`Algorithm::choose`] and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Choice {
+    pub algorithm: Algorithm,
+    /// The estimated memory of count tables, `None` if they can not be used.
+    pub table_bytes: Option<u64>,
+    /// The same for tables of `u32` counters.
+    pub compact_table_bytes: Option<u64>,
+    /// The estimated memory of hash maps, which is saturated if unbounded.
+    pub hash_bytes: u64,
+    /// The memory the counts may use.
+    pub budget: u64,
+    pub available_memory: Option<u64>,
+}
+
+impl fmt::Display for Choice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gib = |bytes: u64| bytes as f64 / (1u64 << 30) as f64;
+        write!(f, "chose {}: ", self.algorithm)?;
+        match (self.table_bytes, self.compact_table_bytes) {
+            (Some(bytes), Some(compact)) => write!(
+                f,
+                "count tables need {:.3} GiB or {:.3} GiB with u32 counters",
+                gib(bytes),
+                gib(compact)
+            )?,
+            _ => write!(f, "count tables can not hold strings this wide")?,
+        }
+        if self.hash_bytes == u64::MAX {
+            write!(f, ", hash maps an unknown amount")?;
+        } else {
+            write!(f, ", hash maps up to {:.3} GiB", gib(self.hash_bytes))?;
+        }
+        write!(f, ", the budget is {:.3} GiB", gib(self.budget))?;
+        match self.available_memory {
+            Some(bytes) => write!(f, " of {:.3} GiB available", gib(bytes)),
+            None => write!(f, " as the available memory is unknown"),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| {
+                let names = Self::ALL.iter().map(|a| a.name()).collect::<Vec<_>>();
+                format!(
+                    "Unsupported algorithm {}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// How a `Variant` packs digit strings, chosen by the number of digits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Packing {
+    /// In a `u64`, up to 16 hex or 19 decimal digits.
+    U64,
+    /// In a `u128`, up to 32 hex or 38 decimal digits.
+    U128,
+    /// Any width, using `WideVariant` with the strings as keys.
+    Wide,
+}
+
+impl Packing {
+    /// A rough size of a hash map entry of strings of up to `digits` digits,
+    /// including unused capacity.
+    fn hash_entry_bytes(self, digits: usize) -> u64 {
+        let key = match self {
+            Packing::U64 => 8,
+            Packing::U128 => 16,
+            // NB: A boxed slice and its heap allocation.
+            Packing::Wide => 16 + 16 + digits as u64,
+        };
+        // NB: Hash maps are between 7/16 and 7/8 full, and briefly keep the
+        // old buckets while growing.
+        (key + std::mem::size_of::<Counter>() as u64 + 1) * 2
+    }
+
+    pub fn for_digits(digits: usize, alphabet: &Alphabet) -> Self {
+        if digits <= u64::max_digits(alphabet.radix()) {
+            Packing::U64
+        } else if digits <= u128::max_digits(alphabet.radix()) {
+            Packing::U128
+        } else {
+            Packing::Wide
+        }
+    }
+}
+
+/// Object safe view of a [`Process`], used by [`DigitCounter`].
+trait DynProcess {
+    fn on_byte(&mut self, b: u8);
+    fn on_chunk(&mut self, chunk: &[u8]);
+    fn finalize(&mut self);
+    fn into_count(self: Box<Self>) -> Counts;
+}
+
+impl<T: Process> DynProcess for T {
+    fn on_byte(&mut self, b: u8) {
+        Process::on_byte(self, b)
+    }
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        Process::on_chunk(self, chunk)
+    }
+    fn finalize(&mut self) {
+        Process::finalize(self)
+    }
+    fn into_count(self: Box<Self>) -> Counts {
+        Process::into_count(*self)
+    }
+}
+
+/// Configures and creates a [`DigitCounter`].
+#[derive(Clone, Debug)]
+pub struct CounterBuilder {
+    digits: usize,
+    algorithm: Algorithm,
+    alphabet: Alphabet,
+    input_size: Option<u64>,
+    available_memory: Option<u64>,
+}
+
+impl CounterBuilder {
+    /// Counts substrings of `digits` and fewer hex digits with `variant-4`.
+    pub fn new(digits: usize) -> Self {
+        Self {
+            digits,
+            algorithm: Algorithm::Variant4,
+            alphabet: Alphabet::hex(),
+            input_size: None,
+            available_memory: None,
+        }
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    /// The size of the input in bytes, for [`Algorithm::Auto`].
+    pub fn input_size(mut self, input_size: u64) -> Self {
+        self.input_size = Some(input_size);
+        self
+    }
+
+    /// The memory the counts may use, for [`Algorithm::Auto`].
+    pub fn available_memory(mut self, available_memory: u64) -> Self {
+        self.available_memory = Some(available_memory);
+        self
+    }
+
+    /// The algorithm that will be built, resolving [`Algorithm::Auto`].
+    pub fn choice(&self) -> Choice {
+        let mut choice = Algorithm::choose(
+            self.digits,
+            &self.alphabet,
+            self.input_size,
+            self.available_memory,
+            1,
+        );
+        if self.algorithm != Algorithm::Auto {
+            choice.algorithm = self.algorithm;
+        }
+        choice
+    }
+
+    /// Creates the counter, failing if the algorithm can not count
+    /// `digits` wide substrings of the alphabet.
+    pub fn build(self) -> Result<DigitCounter> {
+        let digits = self.digits;
+        let alphabet = &self.alphabet;
+        let algorithm = self.choice().algorithm;
+        algorithm.check_alphabet(alphabet)?;
+        algorithm.check_width(digits, alphabet)?;
+        let process: Box<dyn DynProcess> = match algorithm {
+            Algorithm::Original => {
+                Box::new(Original::<original::StdNumeric>::new(digits, alphabet))
+            }
+            Algorithm::OriginalHex => {
+                Box::new(Original::<original::AlphabetDigit>::new(digits, alphabet))
+            }
+            Algorithm::Variant1 => variant_process::<
+                variant::EarlyCount,
+                variant::HashMapCounter,
+                variant::HashMapCounter<u128>,
+            >(digits, alphabet),
+            Algorithm::Variant2 => variant_process::<
+                variant::LateCount,
+                variant::HashMapCounter,
+                variant::HashMapCounter<u128>,
+            >(digits, alphabet),
+            Algorithm::Variant3 => {
+                variant_process::<variant::EarlyCount, variant::VecCounter, variant::VecCounter<u128>>(
+                    digits, alphabet,
+                )
+            }
+            Algorithm::Variant4 => {
+                variant_process::<variant::LateCount, variant::VecCounter, variant::VecCounter<u128>>(
+                    digits, alphabet,
+                )
+            }
+            Algorithm::Variant4U32 => variant_process::<
+                variant::LateCount,
+                variant::CompactVecCounter<u64, u32>,
+                variant::CompactVecCounter<u128, u32>,
+            >(digits, alphabet),
+            Algorithm::Variant4U16 => variant_process::<
+                variant::LateCount,
+                variant::CompactVecCounter<u64, u16>,
+                variant::CompactVecCounter<u128, u16>,
+            >(digits, alphabet),
+            Algorithm::Auto => unreachable!("resolved by the choice"),
+        };
+        Ok(DigitCounter {
+            process,
+            finalized: false,
+        })
+    }
+}
+
+/// Creates a `Variant` with the packing chosen by [`Packing::for_digits`],
+/// using storage `U` for `u64` and `W` for `u128` numbers.
+fn variant_process<T, U, W>(digits: usize, alphabet: &Alphabet) -> Box<dyn DynProcess>
+where
+    T: CountStrategy + 'static,
+    U: for<'a> CounterStorage<'a, u64> + 'static,
+    W: for<'a> CounterStorage<'a, u128> + 'static,
+{
+    match Packing::for_digits(digits, alphabet) {
+        Packing::U64 => Box::new(Variant::<T, U, u64>::new(digits, alphabet)),
+        Packing::U128 => Box::new(Variant::<T, W, u128>::new(digits, alphabet)),
+        Packing::Wide => Box::new(WideVariant::new(digits, alphabet)),
+    }
+}
+
+/// A running count created by [`CounterBuilder`].
+pub struct DigitCounter {
+    process: Box<dyn DynProcess>,
+    finalized: bool,
+}
+
+impl DigitCounter {
+    pub fn on_byte(&mut self, b: u8) {
+        self.process.on_byte(b);
+    }
+
+    pub fn on_bytes(&mut self, bytes: &[u8]) {
+        self.process.on_chunk(bytes);
+    }
+
+    /// Flushes the engine after the last byte, without building the counts
+    /// yet. Only the first call has an effect.
+    pub fn finalize(&mut self) {
+        if !self.finalized {
+            self.process.finalize();
+            self.finalized = true;
+        }
+    }
+
+    /// Flushes the engine and returns the final counts.
+    pub fn finish(mut self) -> Counts {
+        self.finalize();
+        self.process.into_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    #[test]
+    fn test_memory_estimate() {
+        let hex = Alphabet::hex();
+        // NB: 1 + 16 + 256 counters.
+        assert_eq!(
+            Algorithm::Variant4.memory_estimate(2, &hex, None),
+            Some(273 * 8)
+        );
+        assert_eq!(
+            Algorithm::Variant2.memory_estimate(2, &hex, Some(10)),
+            Some((1 + 10 + 9) * 34)
+        );
+        assert_eq!(
+            Algorithm::Variant4U16.memory_estimate(2, &hex, None),
+            Some(273 * 2)
+        );
+        assert_eq!(Algorithm::Original.memory_estimate(2, &hex, None), None);
+    }
+
+    #[test]
+    fn test_choose() {
+        let hex = Alphabet::hex();
+        let choose = |digits, input_size, memory| {
+            Algorithm::choose(digits, &hex, input_size, Some(memory), 1).algorithm
+        };
+        assert_eq!(choose(6, Some(GIB), 8 * GIB), Algorithm::Variant4);
+        // NB: 32 GiB of tables for a small input.
+        assert_eq!(choose(8, Some(1 << 20), 8 * GIB), Algorithm::Variant2);
+        assert_eq!(choose(8, Some(1 << 40), 64 * GIB), Algorithm::Variant4);
+        assert_eq!(choose(8, Some(1 << 40), 32 * GIB), Algorithm::Variant4U32);
+        // NB: Nothing fits, the compact tables are smallest.
+        assert_eq!(choose(8, Some(1 << 40), 8 * GIB), Algorithm::Variant4U32);
+        assert_eq!(choose(40, Some(GIB), 8 * GIB), Algorithm::Variant2);
+
+        let choice = Algorithm::choose(3, &hex, None, None, 4);
+        assert_eq!(choice.table_bytes, Some(4369 * 8 * 4));
+        assert_eq!(choice.budget, DEFAULT_MEMORY_BUDGET);
+        assert!(choice.to_string().starts_with("chose variant-4: "));
+    }
+
+    #[test]
+    fn test_builder_width() {
+        let built = CounterBuilder::new(0)
+            .algorithm(Algorithm::Variant4)
+            .build();
+        assert!(matches!(
+            built,
+            Err(Error::UnsupportedWidth { digits: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_builder_alphabet() {
+        let build = |alphabet: Alphabet| {
+            CounterBuilder::new(2)
+                .algorithm(Algorithm::Original)
+                .alphabet(alphabet)
+                .build()
+        };
+        assert!(matches!(build(Alphabet::hex()), Err(Error::InvalidArgs(_))));
+        assert!(build(Alphabet::decimal()).is_ok());
+    }
+
+    #[test]
+    fn test_builder_auto() {
+        let mut counter = CounterBuilder::new(2)
+            .algorithm(Algorithm::Auto)
+            .input_size(4)
+            .build()
+            .unwrap();
+        counter.on_bytes(b"1212");
+        assert_eq!(counter.finish().get(b"12"), 2);
+    }
+}
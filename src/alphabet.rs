@@ -0,0 +1,189 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Marks bytes that are not a digit in a digit map.
+pub const NO_DIGIT: u8 = 0xff;
+
+/// The digit symbols of a base, in order of their value.
+///
+/// Every byte maps to its digit value, or to [`NO_DIGIT`]. The presets
+/// with letters also accept them in upper case, but substrings are always
+/// reported with the first symbol given for a digit.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Alphabet {
+    map: [u8; 256],
+    symbols: Vec<u8>,
+}
+
+impl Alphabet {
+    pub const PRESETS: &'static [&'static str] = &["binary", "octal", "decimal", "hex", "base36"];
+
+    /// Creates an alphabet with the digit symbols `symbols`.
+    ///
+    /// Needs at least 2 and at most 255 distinct symbols.
+    pub fn new(symbols: &[u8]) -> Result<Self, String> {
+        if symbols.len() < 2 || symbols.len() > NO_DIGIT as usize {
+            return Err(format!(
+                "an alphabet needs between 2 and {} symbols, got {}",
+                NO_DIGIT,
+                symbols.len()
+            ));
+        }
+        let mut map = [NO_DIGIT; 256];
+        for (digit, &symbol) in symbols.iter().enumerate() {
+            if map[symbol as usize] != NO_DIGIT {
+                return Err(format!(
+                    "symbol {:?} appears twice in the alphabet",
+                    symbol as char
+                ));
+            }
+            map[symbol as usize] = digit as u8;
+        }
+        Ok(Self {
+            map,
+            symbols: symbols.to_vec(),
+        })
+    }
+
+    /// Also accepts the upper case form of all lower case symbols.
+    fn with_upper_case(mut self) -> Self {
+        for (digit, &symbol) in self.symbols.iter().enumerate() {
+            let upper = symbol.to_ascii_uppercase();
+            if self.map[upper as usize] == NO_DIGIT {
+                self.map[upper as usize] = digit as u8;
+            }
+        }
+        self
+    }
+
+    fn preset(symbols: &[u8]) -> Self {
+        Self::new(symbols).unwrap().with_upper_case()
+    }
+
+    pub fn binary() -> Self {
+        Self::preset(b"01")
+    }
+
+    pub fn octal() -> Self {
+        Self::preset(b"01234567")
+    }
+
+    pub fn decimal() -> Self {
+        Self::preset(b"0123456789")
+    }
+
+    pub fn hex() -> Self {
+        Self::preset(b"0123456789abcdef")
+    }
+
+    pub fn base36() -> Self {
+        Self::preset(b"0123456789abcdefghijklmnopqrstuvwxyz")
+    }
+
+    pub fn radix(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Bits per digit if the radix is a power of two.
+    pub fn bits_per_digit(&self) -> Option<u32> {
+        let radix = self.radix();
+        if radix.is_power_of_two() {
+            Some(radix.trailing_zeros())
+        } else {
+            None
+        }
+    }
+
+    /// Maps every byte to its digit value or [`NO_DIGIT`].
+    pub fn digit_map(&self) -> &[u8; 256] {
+        &self.map
+    }
+
+    pub fn digit(&self, byte: u8) -> Option<u8> {
+        match self.map[byte as usize] {
+            NO_DIGIT => None,
+            v => Some(v),
+        }
+    }
+
+    pub fn symbols(&self) -> &[u8] {
+        &self.symbols
+    }
+
+    pub fn symbol(&self, digit: u8) -> u8 {
+        self.symbols[digit as usize]
+    }
+
+    /// Returns the canonical symbol for `byte` if it is a digit.
+    pub fn normalize(&self, byte: u8) -> u8 {
+        match self.digit(byte) {
+            Some(v) => self.symbol(v),
+            None => byte,
+        }
+    }
+}
+
+impl Default for Alphabet {
+    fn default() -> Self {
+        Self::hex()
+    }
+}
+
+impl fmt::Debug for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Alphabet")
+            .field(&String::from_utf8_lossy(&self.symbols))
+            .finish()
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = String;
+
+    /// Parses a preset name, or else takes the string as the digit symbols.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(Self::binary()),
+            "octal" => Ok(Self::octal()),
+            "decimal" => Ok(Self::decimal()),
+            "hex" => Ok(Self::hex()),
+            "base36" => Ok(Self::base36()),
+            symbols => Self::new(symbols.as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let map = Alphabet::hex().map;
+        assert_eq!(map[b'a' as usize], 10);
+        assert_eq!(map[b'f' as usize], 15);
+        assert_eq!(map[b'A' as usize], 10);
+        assert_eq!(map[b'F' as usize], 15);
+        assert_eq!(map[b'0' as usize], 0);
+        assert_eq!(map[b'9' as usize], 9);
+        assert_eq!(map[b'_' as usize], 255);
+    }
+
+    #[test]
+    fn test_parse() {
+        let decimal: Alphabet = "decimal".parse().unwrap();
+        assert_eq!(decimal.radix(), 10);
+        assert_eq!(decimal.bits_per_digit(), None);
+        assert_eq!(decimal.digit(b'a'), None);
+        assert_eq!("base36".parse::<Alphabet>().unwrap().digit(b'Z'), Some(35));
+
+        let custom: Alphabet = "ACGT".parse().unwrap();
+        assert_eq!(custom.radix(), 4);
+        assert_eq!(custom.bits_per_digit(), Some(2));
+        assert_eq!(custom.digit(b'G'), Some(2));
+        assert_eq!(custom.digit(b'g'), None);
+
+        assert!("aa".parse::<Alphabet>().is_err());
+        assert!("a".parse::<Alphabet>().is_err());
+    }
+}
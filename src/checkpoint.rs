@@ -0,0 +1,223 @@
+//! Checkpoints of a running count, to resume it after a crash.
+//!
+//! A checkpoint file starts with a text line describing the count, so
+//! `head -1` shows what it belongs to, followed by the binary engine state
+//! written by [`Process::save_state`](crate::Process::save_state).
+
+use crate::select::{ByteRange, Selection};
+use std::io::{self, BufRead, Read, Write};
+
+const CHECKPOINT_HEADER: &str = "count-digits checkpoint v1";
+
+/// Where a count was interrupted, and what it was counting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckpointInfo {
+    pub algorithm: String,
+    pub digits: usize,
+    /// Symbols of the alphabet.
+    pub alphabet: Vec<u8>,
+    pub selection: Selection,
+    /// Offset after the marker of the next byte to count.
+    pub offset: u64,
+}
+
+impl CheckpointInfo {
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        let marker = self
+            .selection
+            .marker
+            .as_deref()
+            .map_or("none".to_owned(), hex);
+        let max_digits = self.selection.max_digits;
+        writeln!(
+            w,
+            "{} algorithm={} digits={} alphabet={} marker={} range={} max-digits={} offset={}",
+            CHECKPOINT_HEADER,
+            self.algorithm,
+            self.digits,
+            hex(&self.alphabet),
+            marker,
+            self.selection.range,
+            max_digits.map_or("none".to_owned(), |n| n.to_string()),
+            self.offset
+        )
+    }
+
+    /// Reads the header line, leaving `r` at the start of the engine state.
+    pub fn read_from(mut r: impl BufRead) -> io::Result<Self> {
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        let fields = line
+            .trim_end()
+            .strip_prefix(CHECKPOINT_HEADER)
+            .ok_or_else(|| invalid_data(format!("not a checkpoint, header is {:?}", line)))?;
+
+        let field = |key: &str| {
+            fields
+                .split(' ')
+                .find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+                .ok_or_else(|| invalid_data(format!("checkpoint header lacks {}", key)))
+        };
+        let invalid = |key: &str| invalid_data(format!("invalid {} in checkpoint header", key));
+        let number = |key: &str| field(key)?.parse::<u64>().map_err(|_| invalid(key));
+
+        let selection = Selection {
+            marker: match field("marker")? {
+                "none" => None,
+                marker => Some(unhex(marker).ok_or_else(|| invalid("marker"))?),
+            },
+            range: field("range")?
+                .parse::<ByteRange>()
+                .map_err(|_| invalid("range"))?,
+            max_digits: match field("max-digits")? {
+                "none" => None,
+                _ => Some(number("max-digits")?),
+            },
+        };
+        Ok(Self {
+            algorithm: field("algorithm")?.to_owned(),
+            digits: number("digits")? as usize,
+            alphabet: unhex(field("alphabet")?).ok_or_else(|| invalid("alphabet"))?,
+            selection,
+            offset: number("offset")?,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub(crate) fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The error of engines that can not be checkpointed.
+pub(crate) fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "this algorithm does not support checkpoints",
+    )
+}
+
+pub(crate) fn write_u64(w: &mut dyn Write, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn read_u64(r: &mut dyn Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn write_u128(w: &mut dyn Write, v: u128) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn read_u128(r: &mut dyn Read) -> io::Result<u128> {
+    let mut buf = [0; 16];
+    r.read_exact(&mut buf)?;
+    Ok(u128::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::{EarlyCount, HashMapCounter, LateCount, Variant, VecCounter};
+    use crate::wide::WideVariant;
+    use crate::{Alphabet, Counts, Process};
+
+    /// Counts `input` in one go, and with a save and load after every byte.
+    fn check_resume<T: Process>(digits: usize, input: &[u8]) {
+        let alphabet = Alphabet::hex();
+        let mut expected = T::new(digits, &alphabet);
+        for &b in input {
+            expected.on_byte(b);
+        }
+        expected.finalize();
+        let expected: Counts = expected.into_count();
+
+        let mut imp = T::new(digits, &alphabet);
+        for &b in input {
+            imp.on_byte(b);
+            let mut state = Vec::new();
+            imp.save_state(&mut state).unwrap();
+            imp = T::new(digits, &alphabet);
+            imp.load_state(&mut &state[..]).unwrap();
+        }
+        imp.finalize();
+        assert_eq!(imp.into_count(), expected);
+    }
+
+    #[test]
+    fn test_resume() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        for digits in 1..5 {
+            check_resume::<Variant<EarlyCount, HashMapCounter>>(digits, input);
+            check_resume::<Variant<LateCount, VecCounter>>(digits, input);
+            check_resume::<Variant<LateCount, HashMapCounter<u128>, u128>>(digits, input);
+            check_resume::<WideVariant>(digits, input);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_state() {
+        // NB: A register, then `(count, number)` lists of widths 1 and 2,
+        // each ending with a zero count.
+        let state = |register: u128, digits: u64, number: u128| {
+            let mut state = Vec::new();
+            write_u128(&mut state, register).unwrap();
+            write_u64(&mut state, digits).unwrap();
+            for &v in &[1, number] {
+                write_u64(&mut state, 1).unwrap();
+                write_u128(&mut state, v).unwrap();
+                write_u64(&mut state, 0).unwrap();
+            }
+            state
+        };
+        let load = |state: &[u8]| {
+            let mut imp = Variant::<LateCount, VecCounter>::new(2, &Alphabet::hex());
+            imp.load_state(&mut &state[..])
+        };
+        assert!(load(&state(0xa, 1, 0xff)).is_ok());
+        assert!(load(&state(0xa, 1, 1_000_000_000)).is_err());
+        assert!(load(&state(0xa, 1, 0x100)).is_err());
+        assert!(load(&state(0xaa, 1, 0xff)).is_err());
+        assert!(load(&state(1 << 64, 2, 0xff)).is_err());
+        assert!(load(&state(0xa, 3, 0xff)).is_err());
+    }
+
+    #[test]
+    fn test_info_roundtrip() {
+        let info = CheckpointInfo {
+            algorithm: "variant-4".to_owned(),
+            digits: 7,
+            alphabet: b"0123456789".to_vec(),
+            selection: Selection {
+                marker: Some(b"\r\n".to_vec()),
+                range: "5..1000".parse().unwrap(),
+                max_digits: Some(100),
+            },
+            offset: 1 << 40,
+        };
+        let mut buf = Vec::new();
+        info.write_to(&mut buf).unwrap();
+        buf.extend_from_slice(b"\x00state");
+
+        let mut r = &buf[..];
+        assert_eq!(CheckpointInfo::read_from(&mut r).unwrap(), info);
+        assert_eq!(r, b"\x00state");
+        assert!(CheckpointInfo::read_from(&b"count-digits counts digits=3\n"[..]).is_err());
+
+        let no_selection =
+            b"count-digits checkpoint v1 algorithm=variant-2 digits=3 alphabet=3031 offset=7\n";
+        assert!(CheckpointInfo::read_from(&no_selection[..]).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Vectorized mapping of bytes to digit values.
+//!
+//! An alphabet is split into ranges of consecutive bytes with consecutive
+//! digit values, like `0-9`, `a-f` and `A-F` for hex. A byte is a digit
+//! if it falls into one of the ranges, which SSE2 and AVX2 can check for
+//! 16 or 32 bytes at once. Alphabets with too many ranges, and CPUs
+//! without either extension, use the scalar digit map.
+
+use crate::alphabet::{Alphabet, NO_DIGIT};
+use std::fmt;
+
+/// Number of bytes classified at once.
+pub(crate) const BLOCK: usize = 32;
+
+/// Alphabets with more ranges are classified by the scalar digit map.
+const MAX_RANGES: usize = 4;
+
+/// The instruction set used to classify bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SimdLevel {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl SimdLevel {
+    /// The best level supported by the running CPU.
+    pub fn detect() -> Self {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return SimdLevel::Avx2;
+            }
+            if is_x86_feature_detected!("sse2") {
+                return SimdLevel::Sse2;
+            }
+        }
+        SimdLevel::Scalar
+    }
+
+    /// The level used to classify digits of `alphabet`.
+    pub fn for_alphabet(alphabet: &Alphabet) -> Self {
+        Classifier::new(alphabet).level()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SimdLevel::Scalar => "scalar",
+            SimdLevel::Sse2 => "sse2",
+            SimdLevel::Avx2 => "avx2",
+        }
+    }
+}
+
+impl fmt::Display for SimdLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The bytes `start..start + len` map to the digits `first_digit..`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DigitRange {
+    start: u8,
+    len: u8,
+    first_digit: u8,
+}
+
+/// Maps blocks of bytes to digit values.
+#[derive(Clone)]
+pub(crate) struct Classifier {
+    map: [u8; 256],
+    ranges: Vec<DigitRange>,
+    level: SimdLevel,
+}
+
+impl Classifier {
+    /// Uses the best level supported by both the CPU and `alphabet`.
+    pub(crate) fn new(alphabet: &Alphabet) -> Self {
+        Self::with_level(alphabet, SimdLevel::detect())
+    }
+
+    /// Uses `level` if `alphabet` allows, and the scalar map otherwise.
+    ///
+    /// `level` must be supported by the running CPU.
+    pub(crate) fn with_level(alphabet: &Alphabet, level: SimdLevel) -> Self {
+        let map = *alphabet.digit_map();
+        let ranges = digit_ranges(&map);
+        let level = if ranges.len() > MAX_RANGES {
+            SimdLevel::Scalar
+        } else {
+            level
+        };
+        Self { map, ranges, level }
+    }
+
+    pub(crate) fn level(&self) -> SimdLevel {
+        self.level
+    }
+
+    /// Writes the digit value or [`NO_DIGIT`] of every byte in `bytes` to
+    /// `values`, and returns a mask with bit `i` set if `bytes[i]` is not
+    /// a digit.
+    #[inline]
+    pub(crate) fn classify(&self, bytes: &[u8; BLOCK], values: &mut [u8; BLOCK]) -> u32 {
+        match self.level {
+            SimdLevel::Scalar => self.classify_scalar(bytes, values),
+            // SAFETY: `level` is only set to levels the CPU supports.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Sse2 => unsafe {
+                let low = x86::classify_sse2(&self.ranges, &bytes[..16], &mut values[..16]);
+                let high = x86::classify_sse2(&self.ranges, &bytes[16..], &mut values[16..]);
+                low | high << 16
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            SimdLevel::Avx2 => unsafe { x86::classify_avx2(&self.ranges, bytes, values) },
+            #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+            _ => self.classify_scalar(bytes, values),
+        }
+    }
+
+    fn classify_scalar(&self, bytes: &[u8; BLOCK], values: &mut [u8; BLOCK]) -> u32 {
+        let mut mask = 0;
+        for (i, (&byte, value)) in bytes.iter().zip(values.iter_mut()).enumerate() {
+            *value = self.map[byte as usize];
+            if *value == NO_DIGIT {
+                mask |= 1 << i;
+            }
+        }
+        mask
+    }
+}
+
+/// Splits a digit map into maximal ranges of consecutive bytes with
+/// consecutive digit values.
+fn digit_ranges(map: &[u8; 256]) -> Vec<DigitRange> {
+    let mut ranges: Vec<DigitRange> = Vec::new();
+    for (byte, &digit) in map.iter().enumerate() {
+        if digit == NO_DIGIT {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(r)
+                if r.start as usize + r.len as usize == byte
+                    && r.first_digit as usize + r.len as usize == digit as usize =>
+            {
+                r.len += 1
+            }
+            _ => ranges.push(DigitRange {
+                start: byte as u8,
+                len: 1,
+                first_digit: digit,
+            }),
+        }
+    }
+    ranges
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    use super::DigitRange;
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    // NB: A byte `b` is in a range if `b - start`, wrapping, is at most
+    // `len - 1`. There is no unsigned compare, but `min(x, y) == x` is one.
+
+    /// Classifies 16 bytes, see `Classifier::classify`.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn classify_sse2(
+        ranges: &[DigitRange],
+        bytes: &[u8],
+        values: &mut [u8],
+    ) -> u32 {
+        assert!(bytes.len() >= 16 && values.len() >= 16);
+        let x = _mm_loadu_si128(bytes.as_ptr() as *const __m128i);
+        let mut found = _mm_setzero_si128();
+        let mut value = _mm_setzero_si128();
+        for r in ranges {
+            let t = _mm_sub_epi8(x, _mm_set1_epi8(r.start as i8));
+            let inside = _mm_cmpeq_epi8(_mm_min_epu8(t, _mm_set1_epi8((r.len - 1) as i8)), t);
+            let digit = _mm_add_epi8(t, _mm_set1_epi8(r.first_digit as i8));
+            value = _mm_or_si128(value, _mm_and_si128(inside, digit));
+            found = _mm_or_si128(found, inside);
+        }
+        // NO_DIGIT is all ones
+        value = _mm_or_si128(value, _mm_andnot_si128(found, _mm_set1_epi8(-1)));
+        _mm_storeu_si128(values.as_mut_ptr() as *mut __m128i, value);
+        !(_mm_movemask_epi8(found) as u32) & 0xffff
+    }
+
+    /// Classifies 32 bytes, see `Classifier::classify`.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn classify_avx2(
+        ranges: &[DigitRange],
+        bytes: &[u8; 32],
+        values: &mut [u8; 32],
+    ) -> u32 {
+        let x = _mm256_loadu_si256(bytes.as_ptr() as *const __m256i);
+        let mut found = _mm256_setzero_si256();
+        let mut value = _mm256_setzero_si256();
+        for r in ranges {
+            let t = _mm256_sub_epi8(x, _mm256_set1_epi8(r.start as i8));
+            let inside =
+                _mm256_cmpeq_epi8(_mm256_min_epu8(t, _mm256_set1_epi8((r.len - 1) as i8)), t);
+            let digit = _mm256_add_epi8(t, _mm256_set1_epi8(r.first_digit as i8));
+            value = _mm256_or_si256(value, _mm256_and_si256(inside, digit));
+            found = _mm256_or_si256(found, inside);
+        }
+        value = _mm256_or_si256(value, _mm256_andnot_si256(found, _mm256_set1_epi8(-1)));
+        _mm256_storeu_si256(values.as_mut_ptr() as *mut __m256i, value);
+        !(_mm256_movemask_epi8(found) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ranges() {
+        let ranges = digit_ranges(Alphabet::hex().digit_map());
+        assert_eq!(
+            ranges,
+            vec![
+                DigitRange {
+                    start: b'0',
+                    len: 10,
+                    first_digit: 0
+                },
+                DigitRange {
+                    start: b'A',
+                    len: 6,
+                    first_digit: 10
+                },
+                DigitRange {
+                    start: b'a',
+                    len: 6,
+                    first_digit: 10
+                },
+            ]
+        );
+        let reversed = Alphabet::new(b"9876543210").unwrap();
+        assert_eq!(digit_ranges(reversed.digit_map()).len(), 10);
+        assert_eq!(SimdLevel::for_alphabet(&reversed), SimdLevel::Scalar);
+    }
+
+    #[test]
+    fn test_levels_agree() {
+        let alphabets = [
+            Alphabet::binary(),
+            Alphabet::decimal(),
+            Alphabet::hex(),
+            Alphabet::base36(),
+            Alphabet::new(b"ACGT").unwrap(),
+            Alphabet::new(&[0, 1, 0xfe, 0xff]).unwrap(),
+        ];
+        let mut bytes = [0u8; BLOCK];
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for alphabet in &alphabets {
+            let scalar = Classifier::with_level(alphabet, SimdLevel::Scalar);
+            let levels = [SimdLevel::Sse2, SimdLevel::Avx2];
+            for &level in levels.iter().filter(|&&l| l <= SimdLevel::detect()) {
+                let simd = Classifier::with_level(alphabet, level);
+                for _ in 0..1000 {
+                    for b in bytes.iter_mut() {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        *b = state as u8;
+                    }
+                    let (mut expected, mut values) = ([0; BLOCK], [0; BLOCK]);
+                    let mask = scalar.classify(&bytes, &mut expected);
+                    assert_eq!(simd.classify(&bytes, &mut values), mask, "{}", level);
+                    assert_eq!(values, expected, "{} {:?}", level, alphabet);
+                }
+            }
+        }
+    }
+}
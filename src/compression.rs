@@ -0,0 +1,188 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Number of bytes needed to tell all formats apart.
+const MAGIC_LEN: usize = 10;
+
+/// A compression format of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Not compressed.
+    Plain,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+impl Compression {
+    pub const ALL: &'static [Compression] = &[
+        Compression::Plain,
+        Compression::Gzip,
+        Compression::Zstd,
+        Compression::Xz,
+        Compression::Bzip2,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Plain => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    /// Detects the format from the first few bytes of a stream.
+    ///
+    /// Anything without a known magic number is `Plain`. The bzip2 magic
+    /// `BZh` is printable, so it also needs the block or end of stream
+    /// marker that follows it.
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(b"BZh")
+            && magic.len() >= MAGIC_LEN
+            && magic[3].is_ascii_digit()
+            && (magic[4..MAGIC_LEN] == [0x31, 0x41, 0x59, 0x26, 0x53, 0x59]
+                || magic[4..MAGIC_LEN] == [0x17, 0x72, 0x45, 0x38, 0x50, 0x90])
+        {
+            Compression::Bzip2
+        } else {
+            Compression::Plain
+        }
+    }
+
+    /// Wraps `input` in a decoder for this format.
+    ///
+    /// Concatenated streams, as written by `cat a.gz b.gz` or parallel
+    /// compressors, are decoded as one.
+    pub fn decoder<'a>(self, input: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Plain => Box::new(input),
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(input)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(input)?),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(input)),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(input)),
+        })
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Compression::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| {
+                let names = Compression::ALL
+                    .iter()
+                    .map(|c| c.name())
+                    .collect::<Vec<_>>();
+                format!(
+                    "unknown compression {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Returns the decompressed contents of `input`.
+///
+/// The format is detected from the magic number unless `compression` is
+/// given. Only the magic number is read up front, the rest is decoded
+/// while reading.
+pub fn decompress<'a>(
+    mut input: impl Read + 'a,
+    compression: Option<Compression>,
+) -> io::Result<(Compression, Box<dyn Read + 'a>)> {
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    (&mut input)
+        .take(MAGIC_LEN as u64)
+        .read_to_end(&mut magic)?;
+    let compression = compression.unwrap_or_else(|| Compression::detect(&magic));
+    let input = io::Cursor::new(magic).chain(input);
+    Ok((compression, compression.decoder(input)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TEXT: &[u8] = b"3.14159265358979323846264338327950288419716939937510";
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::Plain => data.to_vec(),
+            Compression::Gzip => {
+                let mut e = flate2::write::GzEncoder::new(Vec::new(), Default::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+            Compression::Xz => {
+                let mut e = xz2::write::XzEncoder::new(Vec::new(), 6);
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+            Compression::Bzip2 => {
+                let mut e = bzip2::write::BzEncoder::new(Vec::new(), Default::default());
+                e.write_all(data).unwrap();
+                e.finish().unwrap()
+            }
+        }
+    }
+
+    fn read_all(compression: Option<Compression>, data: &[u8]) -> (Compression, Vec<u8>) {
+        let (detected, mut r) = decompress(data, compression).unwrap();
+        let mut out = Vec::new();
+        r.read_to_end(&mut out).unwrap();
+        (detected, out)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for &compression in Compression::ALL {
+            let data = compress(compression, TEXT);
+            assert_eq!(read_all(None, &data), (compression, TEXT.to_vec()));
+            assert_eq!(read_all(Some(compression), &data).1, TEXT);
+
+            // Concatenated streams decode to the concatenated text
+            let twice = [&data[..], &data[..]].concat();
+            assert_eq!(read_all(None, &twice).1, [TEXT, TEXT].concat());
+        }
+    }
+
+    #[test]
+    fn test_forced() {
+        let data = compress(Compression::Gzip, TEXT);
+        assert_eq!(read_all(Some(Compression::Plain), &data).1, data);
+        assert!(decompress(TEXT, Some(Compression::Gzip))
+            .and_then(|(_, mut r)| r.read_to_end(&mut Vec::new()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_detect_short() {
+        assert_eq!(Compression::detect(b""), Compression::Plain);
+        assert_eq!(Compression::detect(b"3"), Compression::Plain);
+        assert_eq!(Compression::detect(b"BZh9 and some"), Compression::Plain);
+        assert_eq!(read_all(None, b"3."), (Compression::Plain, b"3.".to_vec()));
+    }
+}
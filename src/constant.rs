@@ -0,0 +1,429 @@
+//! Digits of mathematical constants, as known inputs.
+//!
+//! Each constant is summed from a series into a fixed-point number with
+//! 32-bit limbs, using only multiplication and division by small integers:
+//! pi with the Bailey-Borwein-Plouffe formula, e with the factorial series
+//! and sqrt(2) with the binomial series of `99/70 * sqrt(1 - 1/9801)`.
+//! Digits in the wanted base are then peeled off by repeated multiplication.
+//! Both steps take time quadratic in the number of digits.
+
+use crate::Alphabet;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Extra fraction limbs that absorb the rounding errors of the series.
+const GUARD_LIMBS: usize = 2;
+
+/// A mathematical constant whose digits can be generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Constant {
+    Pi,
+    E,
+    Sqrt2,
+}
+
+impl Constant {
+    pub const ALL: &'static [Constant] = &[Constant::Pi, Constant::E, Constant::Sqrt2];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Constant::Pi => "pi",
+            Constant::E => "e",
+            Constant::Sqrt2 => "sqrt2",
+        }
+    }
+
+    /// Writes the integer part, a `.` and the first `digits` digits after
+    /// the point, like `3.243f` for pi in hex.
+    pub fn write_to(self, digits: usize, alphabet: &Alphabet, w: impl Write) -> io::Result<()> {
+        let radix = alphabet.radix() as u32;
+        let (chunk, _) = chunk_digits(radix);
+        let padded = digits.div_ceil(chunk) * chunk;
+        let bits = (padded as f64 * f64::from(radix).log2()).ceil() as usize;
+        let limbs = bits / 32 + 1 + GUARD_LIMBS;
+        let value = match self {
+            Constant::Pi => pi(limbs),
+            Constant::E => e(limbs),
+            Constant::Sqrt2 => sqrt2(limbs),
+        };
+        write_digits(value, digits, alphabet, w)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Constant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Constant::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| {
+                let names = Constant::ALL.iter().map(|c| c.name()).collect::<Vec<_>>();
+                format!(
+                    "unknown constant {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Division by a fixed `d` through multiplication with its reciprocal, which
+/// is several times faster than a division instruction.
+struct Divisor {
+    d: u64,
+    reciprocal: u64,
+}
+
+impl Divisor {
+    fn new(d: u32) -> Self {
+        Self {
+            d: u64::from(d),
+            reciprocal: u64::MAX / u64::from(d),
+        }
+    }
+
+    /// Returns the quotient and remainder of `n / d`.
+    fn div_rem(&self, n: u64) -> (u64, u64) {
+        // NB: The estimate is at most two too small, never too large.
+        let mut q = ((u128::from(n) * u128::from(self.reciprocal)) >> 64) as u64;
+        let mut r = n - q * self.d;
+        while r >= self.d {
+            q += 1;
+            r -= self.d;
+        }
+        (q, r)
+    }
+}
+
+/// A non-negative fixed-point number. `limbs[0]` is the integer part, the
+/// others are the fraction, most significant first.
+#[derive(Clone)]
+struct Fixed {
+    limbs: Vec<u32>,
+}
+
+impl Fixed {
+    fn new(integer: u32, limbs: usize) -> Self {
+        let mut limbs = vec![0; limbs];
+        limbs[0] = integer;
+        Self { limbs }
+    }
+
+    fn first_nonzero(&self) -> Option<usize> {
+        self.limbs.iter().position(|&l| l != 0)
+    }
+
+    fn mul_small(&mut self, m: u32) {
+        let mut carry = 0;
+        for limb in self.limbs.iter_mut().rev() {
+            let x = u64::from(*limb) * u64::from(m) + carry;
+            *limb = x as u32;
+            carry = x >> 32;
+        }
+        debug_assert_eq!(carry, 0);
+    }
+
+    fn div_small(&mut self, d: u32) {
+        let divisor = Divisor::new(d);
+        let start = self.first_nonzero().unwrap_or(self.limbs.len());
+        let mut rem = 0;
+        for limb in &mut self.limbs[start..] {
+            let (q, r) = divisor.div_rem(rem << 32 | u64::from(*limb));
+            *limb = q as u32;
+            rem = r;
+        }
+    }
+
+    fn add(&mut self, x: &Fixed) {
+        let start = x.first_nonzero().unwrap_or(x.limbs.len());
+        let mut carry = 0;
+        for (limb, &l) in self.limbs.iter_mut().zip(&x.limbs).skip(start).rev() {
+            let sum = u64::from(*limb) + u64::from(l) + carry;
+            *limb = sum as u32;
+            carry = sum >> 32;
+        }
+        for limb in self.limbs[..start].iter_mut().rev() {
+            if carry == 0 {
+                break;
+            }
+            let sum = u64::from(*limb) + carry;
+            *limb = sum as u32;
+            carry = sum >> 32;
+        }
+    }
+
+    /// Subtracts `x`, which must not be larger.
+    fn sub(&mut self, x: &Fixed) {
+        let start = x.first_nonzero().unwrap_or(x.limbs.len());
+        let mut borrow = false;
+        for (limb, &l) in self.limbs.iter_mut().zip(&x.limbs).skip(start).rev() {
+            let (diff, b1) = limb.overflowing_sub(l);
+            let (diff, b2) = diff.overflowing_sub(u32::from(borrow));
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        for limb in self.limbs[..start].iter_mut().rev() {
+            if !borrow {
+                break;
+            }
+            let (diff, b) = limb.overflowing_sub(1);
+            *limb = diff;
+            borrow = b;
+        }
+    }
+}
+
+/// `pi = sum 16^-k (4/(8k+1) - 2/(8k+4) - 1/(8k+5) - 1/(8k+6))`
+///
+/// As `16^-k` is a single bit, the four quotients are computed side by side
+/// straight from it, and their combination is accumulated without carries.
+fn pi(limbs: usize) -> Fixed {
+    // NB: Each limb of a term is below 2^35 in magnitude, so this holds at
+    // least 2^28 terms, far more than the 8 per limb of precision.
+    let mut acc = vec![0i64; limbs];
+    for k in 0u32.. {
+        let shift = 4 * k as usize;
+        let first = shift.div_ceil(32);
+        if first >= limbs {
+            break;
+        }
+        let divisors = [1, 4, 5, 6].map(|j| Divisor::new(8 * k + j));
+        let mut rems = [0; 4];
+        let mut numerator = 1u64 << (32 * first - shift);
+        for a in &mut acc[first..] {
+            let mut q = [0; 4];
+            for i in 0..4 {
+                let (qi, ri) = divisors[i].div_rem(rems[i] << 32 | numerator);
+                q[i] = qi as i64;
+                rems[i] = ri;
+            }
+            *a += 4 * q[0] - 2 * q[1] - q[2] - q[3];
+            numerator = 0;
+        }
+    }
+
+    let mut pi = Fixed::new(0, limbs);
+    let mut carry = 0;
+    for (limb, &a) in pi.limbs.iter_mut().zip(&acc).rev() {
+        let x = a + carry;
+        *limb = x.rem_euclid(1 << 32) as u32;
+        carry = x.div_euclid(1 << 32);
+    }
+    pi
+}
+
+/// `e = sum 1/k!`
+fn e(limbs: usize) -> Fixed {
+    let mut e = Fixed::new(1, limbs);
+    let mut term = Fixed::new(1, limbs);
+    let mut k = 1;
+    while term.first_nonzero().is_some() {
+        term.div_small(k);
+        e.add(&term);
+        k += 1;
+    }
+    e
+}
+
+/// `sqrt(2) = 99/70 * sqrt(1 - x)` with `x = 1/9801`, where
+/// `sqrt(1 - x) = 1 - sum a_k`, `a_1 = x/2` and
+/// `a_k = a_(k-1) * (2k-3)/(2k) * x`.
+fn sqrt2(limbs: usize) -> Fixed {
+    let mut root = Fixed::new(1, limbs);
+    let mut term = Fixed::new(1, limbs);
+    term.div_small(2 * 9801);
+    let mut k: u32 = 2;
+    while term.first_nonzero().is_some() {
+        root.sub(&term);
+        term.mul_small(2 * k - 3);
+        match (2 * k).checked_mul(9801) {
+            Some(d) => term.div_small(d),
+            None => {
+                term.div_small(2 * k);
+                term.div_small(9801);
+            }
+        }
+        k += 1;
+    }
+    root.mul_small(99);
+    root.div_small(70);
+    root
+}
+
+/// The most digits that fit a `u32` at once, and `radix` to that power.
+fn chunk_digits(radix: u32) -> (usize, u32) {
+    let mut digits = 1;
+    let mut power = radix;
+    while let Some(next) = power.checked_mul(radix) {
+        digits += 1;
+        power = next;
+    }
+    (digits, power)
+}
+
+fn write_digits(
+    mut value: Fixed,
+    digits: usize,
+    alphabet: &Alphabet,
+    mut w: impl Write,
+) -> io::Result<()> {
+    let radix = alphabet.radix() as u32;
+    let mut integer = Vec::new();
+    let mut n = value.limbs[0];
+    loop {
+        integer.push(alphabet.symbol((n % radix) as u8));
+        n /= radix;
+        if n == 0 {
+            break;
+        }
+    }
+    integer.reverse();
+    w.write_all(&integer)?;
+    w.write_all(b".")?;
+
+    let (chunk, power) = chunk_digits(radix);
+    let mut buf = Vec::with_capacity(1 << 16);
+    let mut left = digits;
+    while left != 0 {
+        value.limbs[0] = 0;
+        value.mul_small(power);
+        let mut n = value.limbs[0];
+        let start = buf.len();
+        for _ in 0..chunk {
+            buf.push(alphabet.symbol((n % radix) as u8));
+            n /= radix;
+        }
+        buf[start..].reverse();
+        buf.truncate(start + chunk.min(left));
+        left -= chunk.min(left);
+        if buf.len() >= 1 << 16 {
+            w.write_all(&buf)?;
+            buf.clear();
+        }
+    }
+    w.write_all(&buf)
+}
+
+/// The hex digit of pi at `position` after the point, from 0, with the
+/// BBP digit extraction. Uses `f64`, so it is only exact up to positions
+/// of about ten million.
+pub fn pi_hex_digit(position: u64) -> u8 {
+    let series = |j: u64| {
+        let mut sum = 0.0;
+        for k in 0..=position {
+            let d = 8 * k + j;
+            sum += pow_mod(16, position - k, d) as f64 / d as f64;
+            sum -= sum.floor();
+        }
+        for k in position + 1.. {
+            let term = 16f64.powi(-((k - position) as i32)) / (8 * k + j) as f64;
+            if term < 1e-17 {
+                break;
+            }
+            sum += term;
+        }
+        sum
+    };
+    let x = 4.0 * series(1) - 2.0 * series(4) - series(5) - series(6);
+    ((x - x.floor()) * 16.0) as u8
+}
+
+fn pow_mod(base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    while exponent != 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digits(constant: Constant, digits: usize, alphabet: &Alphabet) -> String {
+        let mut out = Vec::new();
+        constant.write_to(digits, alphabet, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_known_digits() {
+        let hex = Alphabet::hex();
+        let decimal = Alphabet::decimal();
+        let cases = [
+            (
+                Constant::Pi,
+                &hex,
+                "3.243f6a8885a308d313198a2e03707344a4093822299f31d008",
+            ),
+            (
+                Constant::Pi,
+                &decimal,
+                "3.14159265358979323846264338327950288419716939937510",
+            ),
+            (
+                Constant::E,
+                &hex,
+                "2.b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a7",
+            ),
+            (
+                Constant::E,
+                &decimal,
+                "2.71828182845904523536028747135266249775724709369995",
+            ),
+            (
+                Constant::Sqrt2,
+                &hex,
+                "1.6a09e667f3bcc908b2fb1366ea957d3e3adec17512775099da",
+            ),
+            (
+                Constant::Sqrt2,
+                &decimal,
+                "1.41421356237309504880168872420969807856967187537694",
+            ),
+        ];
+        for &(constant, alphabet, expected) in &cases {
+            for &len in &[0, 1, 7, 8, 50] {
+                assert_eq!(
+                    digits(constant, len, alphabet),
+                    expected[..2 + len],
+                    "{} with {} digits",
+                    constant,
+                    len
+                );
+            }
+        }
+        assert_eq!(digits(Constant::Pi, 8, &Alphabet::binary()), "11.00100100");
+    }
+
+    #[test]
+    fn test_bbp_extraction() {
+        let long = digits(Constant::Pi, 3000, &Alphabet::hex());
+        for &position in &[0, 1, 999, 1000, 2047, 2999] {
+            let expected = Alphabet::hex().symbol(pi_hex_digit(position as u64));
+            assert_eq!(long.as_bytes()[2 + position], expected, "at {}", position);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        for &constant in Constant::ALL {
+            assert_eq!(constant.name().parse(), Ok(constant));
+        }
+        assert!("tau".parse::<Constant>().is_err());
+    }
+}
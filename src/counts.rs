@@ -0,0 +1,212 @@
+use crate::{Counter, Error, FastHashMap, Result};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+const COUNTS_HEADER: &str = "count-digits counts digits=";
+
+/// The result of a count: how often each digit substring occurred.
+///
+/// Keys are the substrings as lower case ASCII, so `b"0af"` rather than
+/// nibble values. Substrings that never occurred are absent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    map: FastHashMap<Vec<u8>, Counter>,
+    digits: usize,
+}
+
+impl Counts {
+    /// Creates an empty result for substrings of up to `digits` digits.
+    pub fn new(digits: usize) -> Self {
+        Self::from_map(digits, FastHashMap::default())
+    }
+
+    pub fn from_map(digits: usize, map: FastHashMap<Vec<u8>, Counter>) -> Self {
+        Self { map, digits }
+    }
+
+    /// The maximum substring width that was counted.
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// Returns the count of `substring`, or 0 if it never occurred.
+    pub fn get(&self, substring: &[u8]) -> Counter {
+        self.map.get(substring).copied().unwrap_or(0)
+    }
+
+    /// Adds `delta` to the count of `substring`. Adding 0 leaves an absent
+    /// substring absent.
+    pub fn add(&mut self, substring: &[u8], delta: Counter) {
+        if delta == 0 {
+            return;
+        }
+        if let Some(count) = self.map.get_mut(substring) {
+            *count += delta;
+        } else {
+            self.map.insert(substring.to_vec(), delta);
+        }
+    }
+
+    /// Adds all counts of `other` to `self`.
+    ///
+    /// Merging the counts of several shards gives the counts of their
+    /// concatenation, as long as the shards were split between digit runs.
+    /// Strings crossing a split would otherwise be lost.
+    ///
+    /// Fails with [`Error::InvalidArgs`] if the widths differ.
+    pub fn merge(&mut self, other: &Counts) -> Result<()> {
+        if self.digits != other.digits {
+            return Err(Error::InvalidArgs(format!(
+                "can not merge counts of {} digits into counts of {}",
+                other.digits, self.digits
+            )));
+        }
+        for (k, v) in other.iter() {
+            self.add(k, v);
+        }
+        Ok(())
+    }
+
+    /// Number of distinct substrings with a non-zero count.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], Counter)> {
+        self.map.iter().map(|(k, v)| (&k[..], *v))
+    }
+
+    /// All substrings of exactly `width` digits, sorted.
+    pub fn width(&self, width: usize) -> BTreeMap<&[u8], Counter> {
+        self.iter().filter(|(k, _)| k.len() == width).collect()
+    }
+
+    /// The substrings whose counts differ from those in `other`, with both
+    /// counts, sorted by width and then substring.
+    pub fn differences<'a>(&'a self, other: &'a Counts) -> Vec<(&'a [u8], Counter, Counter)> {
+        let ours = self.iter().map(|(k, v)| (k, v, other.get(k)));
+        let theirs = other
+            .iter()
+            .filter(|&(k, _)| !self.map.contains_key(k))
+            .map(|(k, v)| (k, 0, v));
+        let mut differences = ours
+            .chain(theirs)
+            .filter(|&(_, a, b)| a != b)
+            .collect::<Vec<_>>();
+        differences.sort_unstable_by_key(|&(k, _, _)| (k.len(), k));
+        differences
+    }
+
+    pub fn into_map(self) -> FastHashMap<Vec<u8>, Counter> {
+        self.map
+    }
+
+    /// Writes all counts in a line based text format that keeps the keys,
+    /// for reading back with [`Counts::read_from`].
+    pub fn write_to(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{}{}", COUNTS_HEADER, self.digits)?;
+        for width in 1..self.digits + 1 {
+            for (k, v) in self.width(width) {
+                w.write_all(k)?;
+                writeln!(w, " {}", v)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from(r: impl BufRead) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut lines = r.lines();
+        let header = lines.next().unwrap_or_else(|| Ok(String::new()))?;
+        let digits = header
+            .strip_prefix(COUNTS_HEADER)
+            .and_then(|d| d.parse().ok())
+            .ok_or_else(|| invalid(format!("not a counts file, header is {:?}", header)))?;
+
+        let mut counts = Counts::new(digits);
+        for line in lines {
+            let line = line?;
+            let (k, v) = line
+                .split_once(' ')
+                .and_then(|(k, v)| Some((k, v.parse::<Counter>().ok()?)))
+                .ok_or_else(|| invalid(format!("invalid counts line {:?}", line)))?;
+            if k.is_empty() || k.len() > digits {
+                return Err(invalid(format!("substring {:?} has an invalid width", k)));
+            }
+            counts.add(k.as_bytes(), v);
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(digits: usize, entries: &[(&str, Counter)]) -> Counts {
+        let mut counts = Counts::new(digits);
+        for (k, v) in entries {
+            counts.add(k.as_bytes(), *v);
+        }
+        counts
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = counts(2, &[("1", 2), ("12", 1)]);
+        let b = counts(2, &[("1", 1), ("2", 3)]);
+        let c = counts(2, &[("12", 4), ("ff", 1)]);
+
+        let mut ab_c = a.clone();
+        ab_c.merge(&b).unwrap();
+        ab_c.merge(&c).unwrap();
+
+        let mut bc = b.clone();
+        bc.merge(&c).unwrap();
+        let mut a_bc = a.clone();
+        a_bc.merge(&bc).unwrap();
+
+        assert_eq!(ab_c, a_bc);
+        assert_eq!(ab_c, counts(2, &[("1", 3), ("2", 3), ("12", 5), ("ff", 1)]));
+
+        let wide = counts(3, &[("123", 1)]);
+        assert!(matches!(ab_c.merge(&wide), Err(Error::InvalidArgs(_))));
+        assert_eq!(ab_c, a_bc);
+    }
+
+    #[test]
+    fn test_add_zero() {
+        let zero = counts(2, &[("ff", 0), ("1", 0)]);
+        assert!(zero.is_empty());
+        assert_eq!(zero, Counts::new(2));
+        assert_eq!(counts(2, &[("1", 2), ("1", 0)]).get(b"1"), 2);
+    }
+
+    #[test]
+    fn test_differences() {
+        let a = counts(2, &[("1", 2), ("2", 1), ("12", 1)]);
+        let b = counts(2, &[("1", 2), ("2", 3), ("0a", 1)]);
+        let expected: Vec<(&[u8], Counter, Counter)> =
+            vec![(b"2", 1, 3), (b"0a", 0, 1), (b"12", 1, 0)];
+        assert_eq!(a.differences(&b), expected);
+        assert!(a.differences(&a).is_empty());
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let a = counts(3, &[("0", 7), ("a0", 1), ("fff", 12345678901)]);
+        let mut buf = Vec::new();
+        a.write_to(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "count-digits counts digits=3\n0 7\na0 1\nfff 12345678901\n"
+        );
+        assert_eq!(Counts::read_from(&buf[..]).unwrap(), a);
+        assert!(Counts::read_from(&b"0 7\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Everything that can make a count fail.
+///
+/// Each kind has its own process exit code, see [`Error::exit_code`].
+#[derive(Debug)]
+pub enum Error {
+    /// Reading the input or writing a result failed. Usually worth a retry.
+    Io {
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+    /// The command line does not make sense.
+    InvalidArgs(String),
+    /// The algorithm can not count substrings this wide.
+    UnsupportedWidth { digits: usize, reason: String },
+    /// An output path would have to be derived from a path that is not
+    /// valid UTF-8.
+    InvalidUtf8Path(PathBuf),
+    /// An output file already exists and may not be overwritten.
+    OutputExists(PathBuf),
+    /// Algorithms that were verified against each other counted differently.
+    CountsDiffer(String),
+}
+
+impl Error {
+    /// Returns a closure wrapping an I/O error on `path`, for `map_err`.
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Io {
+            path: Some(path.to_owned()),
+            source,
+        }
+    }
+
+    /// The process exit code for this error.
+    ///
+    /// | Code | Error                  |
+    /// |------|------------------------|
+    /// | 1    | `Io`                   |
+    /// | 2    | `InvalidArgs`          |
+    /// | 3    | `UnsupportedWidth`     |
+    /// | 4    | `InvalidUtf8Path`      |
+    /// | 5    | `OutputExists`         |
+    /// | 6    | `CountsDiffer`         |
+    ///
+    /// Panics exit with 101 as usual.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 1,
+            Error::InvalidArgs(_) => 2,
+            Error::UnsupportedWidth { .. } => 3,
+            Error::InvalidUtf8Path(_) => 4,
+            Error::OutputExists(_) => 5,
+            Error::CountsDiffer(_) => 6,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::InvalidArgs(msg) => write!(f, "{}", msg),
+            Error::UnsupportedWidth { digits, reason } => {
+                write!(f, "can not count {} digits: {}", digits, reason)
+            }
+            Error::InvalidUtf8Path(path) => {
+                write!(f, "path is not valid UTF-8: {}", path.display())
+            }
+            Error::OutputExists(path) => {
+                write!(f, "output file already exists: {}", path.display())
+            }
+            Error::CountsDiffer(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
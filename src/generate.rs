@@ -0,0 +1,314 @@
+//! Reproducible random inputs for tests and benchmarks.
+//!
+//! Inputs are a prefix like `1.` followed by runs of uniformly random
+//! digits separated by noise bytes, which are never digits. Everything is
+//! derived from a seed, so the same options always give the same bytes.
+
+use crate::{Alphabet, Error, Result};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// The xoshiro256** generator, seeded through SplitMix64.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Self {
+            state: [next(), next(), next(), next()],
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A number in `0..n`, for `n > 0`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // NB: The bias of at most n / 2^64 does not matter here.
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// How long the runs of digits between noise bytes are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunLengths {
+    /// Every byte is noise with the noise probability, so runs have a
+    /// geometric length.
+    Geometric,
+    /// Runs of exactly this many digits.
+    Fixed(u64),
+    /// Runs of `min..=max` digits, uniformly.
+    Uniform { min: u64, max: u64 },
+}
+
+impl fmt::Display for RunLengths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunLengths::Geometric => f.write_str("geometric"),
+            RunLengths::Fixed(n) => write!(f, "fixed:{}", n),
+            RunLengths::Uniform { min, max } => write!(f, "uniform:{}..{}", min, max),
+        }
+    }
+}
+
+impl FromStr for RunLengths {
+    type Err = String;
+
+    /// Parses `geometric`, `fixed:N` or `uniform:MIN..MAX`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid run lengths {:?}, expected geometric, fixed:N or uniform:MIN..MAX",
+                s
+            )
+        };
+        let (kind, args) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "geometric" if args.is_empty() => Ok(RunLengths::Geometric),
+            "fixed" => Ok(RunLengths::Fixed(args.parse().map_err(|_| invalid())?)),
+            "uniform" => {
+                let (min, max) = args.split_once("..").ok_or_else(invalid)?;
+                let min = min.parse().map_err(|_| invalid())?;
+                let max = max.parse().map_err(|_| invalid())?;
+                if max < min {
+                    return Err(format!("run lengths {:?} end before they start", s));
+                }
+                Ok(RunLengths::Uniform { min, max })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A number of bytes with an optional binary `K`, `M`, `G` or `T` suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid size {:?}, expected a number like 512, 64K or 1G",
+                s
+            )
+        };
+        let (number, shift) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&s[..s.len() - 1], 10),
+            Some('M') => (&s[..s.len() - 1], 20),
+            Some('G') => (&s[..s.len() - 1], 30),
+            Some('T') => (&s[..s.len() - 1], 40),
+            _ => (s, 0),
+        };
+        let number: u64 = number.parse().map_err(|_| invalid())?;
+        number
+            .checked_mul(1 << shift)
+            .map(ByteSize)
+            .ok_or_else(invalid)
+    }
+}
+
+/// Produces the random bytes after the prefix.
+#[derive(Clone, Debug)]
+pub struct Generator {
+    rng: Rng,
+    symbols: Vec<u8>,
+    noise: Vec<u8>,
+    noise_probability: f64,
+    runs: RunLengths,
+    /// Digits left in the current run, for non-geometric runs.
+    run_left: u64,
+}
+
+impl Generator {
+    /// Creates a generator of digits of `alphabet`. Noise bytes are ASCII
+    /// bytes that are no digits, or any such byte if all of ASCII are.
+    pub fn new(
+        alphabet: &Alphabet,
+        seed: u64,
+        noise_probability: f64,
+        runs: RunLengths,
+    ) -> Result<Self> {
+        if !(0.0..=1.0).contains(&noise_probability) {
+            return Err(Error::InvalidArgs(format!(
+                "the noise probability {} is not between 0 and 1",
+                noise_probability
+            )));
+        }
+        let is_noise = |b: &u8| alphabet.digit(*b).is_none();
+        let mut noise = (0..=127).filter(is_noise).collect::<Vec<u8>>();
+        if noise.is_empty() {
+            noise = (128..=255).filter(is_noise).collect();
+        }
+        let needs_noise = match runs {
+            RunLengths::Geometric => noise_probability > 0.0,
+            _ => true,
+        };
+        if noise.is_empty() && needs_noise {
+            return Err(Error::InvalidArgs(
+                "the alphabet leaves no bytes for noise".to_owned(),
+            ));
+        }
+        let mut generator = Self {
+            rng: Rng::new(seed),
+            symbols: alphabet.symbols().to_vec(),
+            noise,
+            noise_probability,
+            runs,
+            run_left: 0,
+        };
+        generator.run_left = generator.next_run();
+        Ok(generator)
+    }
+
+    fn next_run(&mut self) -> u64 {
+        match self.runs {
+            RunLengths::Geometric => 0,
+            RunLengths::Fixed(n) => n,
+            RunLengths::Uniform { min, max } => match (max - min).checked_add(1) {
+                Some(n) => min + self.rng.below(n),
+                None => self.rng.next_u64(),
+            },
+        }
+    }
+
+    fn noise_byte(&mut self) -> u8 {
+        self.noise[self.rng.below(self.noise.len() as u64) as usize]
+    }
+
+    fn digit_byte(&mut self) -> u8 {
+        self.symbols[self.rng.below(self.symbols.len() as u64) as usize]
+    }
+
+    /// Fills `buf` with the next bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for b in buf {
+            *b = match self.runs {
+                RunLengths::Geometric => {
+                    if self.noise_probability > 0.0 && self.rng.next_f64() < self.noise_probability
+                    {
+                        self.noise_byte()
+                    } else {
+                        self.digit_byte()
+                    }
+                }
+                _ if self.run_left == 0 => {
+                    self.run_left = self.next_run();
+                    self.noise_byte()
+                }
+                _ => {
+                    self.run_left -= 1;
+                    self.digit_byte()
+                }
+            };
+        }
+    }
+
+    /// Writes `prefix` followed by `size` generated bytes.
+    pub fn write_to(&mut self, prefix: &[u8], size: u64, mut w: impl Write) -> io::Result<()> {
+        w.write_all(prefix)?;
+        let mut buf = vec![0; 1 << 16];
+        let mut left = size;
+        while left != 0 {
+            let len = left.min(buf.len() as u64) as usize;
+            self.fill(&mut buf[..len]);
+            w.write_all(&buf[..len])?;
+            left -= len as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Stats;
+
+    fn generate(alphabet: &Alphabet, seed: u64, noise: f64, runs: RunLengths) -> Vec<u8> {
+        let mut out = Vec::new();
+        Generator::new(alphabet, seed, noise, runs)
+            .unwrap()
+            .write_to(b"1.", 100_000, &mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_reproducible() {
+        let hex = Alphabet::hex();
+        let a = generate(&hex, 7, 0.01, RunLengths::Geometric);
+        assert_eq!(a.len(), 100_002);
+        assert!(a.starts_with(b"1."));
+        assert_eq!(a, generate(&hex, 7, 0.01, RunLengths::Geometric));
+        assert_ne!(a, generate(&hex, 8, 0.01, RunLengths::Geometric));
+
+        let noise = a[2..].iter().filter(|&&b| hex.digit(b).is_none()).count();
+        assert!((800..1200).contains(&noise), "{} noise bytes", noise);
+        let mut counts = crate::Counts::new(1);
+        for &b in &a[2..] {
+            if hex.digit(b).is_some() {
+                counts.add(&[b], 1);
+            }
+        }
+        assert!(Stats::from_counts(&counts, 16, 1).p_value > 1e-4);
+    }
+
+    #[test]
+    fn test_run_lengths() {
+        let decimal = Alphabet::decimal();
+        let runs = |bytes: &[u8]| {
+            let runs = bytes[2..].split(|&b| decimal.digit(b).is_none());
+            let mut lengths = runs.map(|r| r.len()).collect::<Vec<_>>();
+            lengths.pop();
+            lengths
+        };
+        let fixed = generate(&decimal, 1, 0.5, RunLengths::Fixed(5));
+        assert!(runs(&fixed).iter().all(|&n| n == 5));
+        let uniform = generate(&decimal, 1, 0.0, "uniform:2..4".parse().unwrap());
+        let lengths = runs(&uniform);
+        assert!(lengths.iter().all(|&n| (2..=4).contains(&n)));
+        assert!((2..=4).all(|n| lengths.contains(&n)));
+        assert!(runs(&generate(&decimal, 1, 0.0, RunLengths::Geometric)).is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("geometric".parse(), Ok(RunLengths::Geometric));
+        assert_eq!("fixed:3".parse(), Ok(RunLengths::Fixed(3)));
+        let uniform = "uniform:1..8".parse::<RunLengths>().unwrap();
+        assert_eq!(uniform.to_string(), "uniform:1..8");
+        assert!("uniform:8..1".parse::<RunLengths>().is_err());
+        assert!("poisson".parse::<RunLengths>().is_err());
+        assert_eq!("64K".parse(), Ok(ByteSize(64 << 10)));
+        assert_eq!("3".parse(), Ok(ByteSize(3)));
+        assert!("1X".parse::<ByteSize>().is_err());
+        assert!("99999999999T".parse::<ByteSize>().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! Counts every digit substring up to a given width in a byte stream.
+//!
+//! Digits are hex by default, any other base can be used via [`Alphabet`].
+//! The quickest way in is [`CounterBuilder`], which picks one of the
+//! counting engines by [`Algorithm`] and hands back a [`DigitCounter`]:
+//!
+//! ```
+//! use count_digits::{Algorithm, CounterBuilder};
+//!
+//! let mut counter = CounterBuilder::new(2)
+//!     .algorithm(Algorithm::Variant4)
+//!     .build()?;
+//! counter.on_bytes(b"1212");
+//! let counts = counter.finish();
+//! assert_eq!(counts.get(b"12"), 2);
+//! assert_eq!(counts.get(b"21"), 1);
+//! # Ok::<(), count_digits::Error>(())
+//! ```
+//!
+//! Code that wants static dispatch can use the engines in [`original`],
+//! [`variant`] and [`wide`] directly through the [`Process`] trait.
+
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+use std::io::{self, Read, Write};
+
+mod algorithm;
+mod alphabet;
+pub mod checkpoint;
+mod classify;
+mod compression;
+pub mod constant;
+mod counts;
+mod error;
+pub mod generate;
+mod missing;
+mod offsets;
+pub mod original;
+pub mod parallel;
+mod ranking;
+pub mod reference;
+mod report;
+pub mod search;
+pub mod select;
+mod stats;
+pub mod variant;
+pub mod wide;
+
+pub use algorithm::{Algorithm, Choice, CounterBuilder, DigitCounter, Packing};
+pub use alphabet::{Alphabet, NO_DIGIT};
+pub use classify::SimdLevel;
+pub use compression::{decompress, Compression};
+pub use counts::Counts;
+pub use error::{Error, Result};
+pub use missing::Missing;
+pub use offsets::FirstOffsets;
+pub use ranking::{Order, Ranking};
+pub use report::{BenchReport, BenchResult, Format, MatchWriter, Report};
+pub use stats::Stats;
+
+// NB: We will not exhaust a u64 with modern computers as long as its
+// counted up by 1 at a time.
+pub type Counter = u64;
+
+//type HashFn = std::collections::hash_map::DefaultHasher;
+pub type HashFn = fxhash::FxHasher;
+
+pub type FastHashMap<K, V> = HashMap<K, V, BuildHasherDefault<HashFn>>;
+
+/// A counting engine that is fed one byte at a time.
+pub trait Process {
+    /// Creates an engine counting substrings of `digit` and fewer digits
+    /// of `alphabet`.
+    fn new(digit: usize, alphabet: &Alphabet) -> Self;
+    fn on_byte(&mut self, b: u8);
+    /// Feeds all bytes of `chunk`, like calling `on_byte` for each.
+    ///
+    /// Engines override this where they can process bytes in batches.
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        for &b in chunk {
+            self.on_byte(b);
+        }
+    }
+    /// Flushes any pending state. Must be called once after the last byte.
+    fn finalize(&mut self);
+    fn into_count(self) -> Counts;
+
+    /// The `k` most or least frequent substrings of `width` digits, after
+    /// finalizing.
+    ///
+    /// Engines that can rank without building their [`Counts`] return
+    /// `Some`, for the others use [`Ranking::from_counts`].
+    fn ranking(&mut self, _width: usize, _order: Order, _k: usize) -> Option<Ranking> {
+        None
+    }
+
+    /// The substrings of `width` digits that never occurred, listing up to
+    /// `limit` of them, after finalizing.
+    ///
+    /// Like [`Process::ranking`], engines that can do without their
+    /// [`Counts`] return `Some`, for the others use [`Missing::from_counts`].
+    fn missing(&mut self, _width: usize, _limit: usize) -> Option<Missing> {
+        None
+    }
+
+    /// Goodness of fit statistics of `width` digits, after finalizing.
+    ///
+    /// Like [`Process::ranking`], engines that can do without their
+    /// [`Counts`] return `Some`, for the others use [`Stats::from_counts`].
+    fn stats(&mut self, _width: usize) -> Option<Stats> {
+        None
+    }
+
+    /// Starts recording the offset of the first occurrence of every
+    /// substring, with `start` as the offset of the next byte. Must be
+    /// called before the first byte.
+    ///
+    /// Returns `false` if the engine can not record offsets.
+    fn record_first_offsets(&mut self, _start: u64) -> bool {
+        false
+    }
+
+    /// The recorded first offsets after finalizing, if
+    /// [`Process::record_first_offsets`] was called.
+    fn first_offsets(&mut self) -> Option<FirstOffsets> {
+        None
+    }
+
+    /// Writes the complete state before finalizing, for a checkpoint.
+    ///
+    /// Engines without checkpoint support return an `Unsupported` error.
+    fn save_state(&mut self, _w: &mut dyn Write) -> io::Result<()> {
+        Err(checkpoint::unsupported())
+    }
+
+    /// Restores a state written by [`Process::save_state`] into a newly
+    /// created engine of the same width and alphabet.
+    fn load_state(&mut self, _r: &mut dyn Read) -> io::Result<()> {
+        Err(checkpoint::unsupported())
+    }
+}
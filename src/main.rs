@@ -1,51 +1,70 @@
-use original::Original;
+use count_digits::checkpoint::CheckpointInfo;
+use count_digits::constant::Constant;
+use count_digits::generate::{ByteSize, Generator, RunLengths};
+use count_digits::original::{self, Original};
+use count_digits::search::Searcher;
+use count_digits::select::{parse_marker, ByteRange, Selection, Selector, Step};
+use count_digits::variant::{self, CountStrategy, CounterStorage, Number, Variant};
+use count_digits::wide::WideVariant;
+use count_digits::{
+    decompress, parallel, Algorithm, Alphabet, BenchReport, BenchResult, Compression, Counter,
+    CounterBuilder, Counts, DigitCounter, Error, FirstOffsets, Format, MatchWriter, Missing, Order,
+    Packing, Process, Ranking, Report, Result, SimdLevel, Stats,
+};
 use sha2::{Digest, Sha256};
-use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::iter::FromIterator;
-use std::{hash::BuildHasherDefault, path::PathBuf, time::Instant};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
-use variant::Variant;
-
-mod original;
-mod variant;
 
 mod single_file;
 
-// NB: We will not exhaust a u64 with modern computers as long as its
-// counted up by 1 at a time.
-type Counter = u64;
-
-//type HashFn = std::collections::hash_map::DefaultHasher;
-type HashFn = fxhash::FxHasher;
-
-type FastHashMap<K, V> = HashMap<K, V, BuildHasherDefault<HashFn>>;
-
 const DEBUG_PRINT_COUNTS: bool = false;
 const DEBUG_PRINT_COUNTS2: bool = false;
 const DEBUG_HEX_HISTOGRAM: bool = false;
 const DEBUG_COMPUTE_OUTPUT_HASH: bool = true;
 
-const ERRMSG: &str =
-    "Usage: <algorithm> <path> <digit> [buffer (MiB)]\nNote that maximum supported file size is 2^128-1 bytes.";
 // NB: Capacity used per default by std::BufReader
 const STD_CAPACITY: usize = 8 * 1024;
 // const CAPACITY: usize = 2048;
 const INTERVAL: Counter = 10000000;
 
-trait Process {
-    fn new(digit: usize) -> Self;
-    fn on_byte(&mut self, b: u8);
-    fn finalize(&mut self);
-    fn into_count(self) -> FastHashMap<Vec<u8>, Counter>;
+#[derive(StructOpt, Debug)]
+// NB: clap 2 only passes non UTF-8 paths on to an external subcommand with
+// `StrictUtf8`, the check is inverted there.
+#[structopt(name = "count-digits", setting = AppSettings::StrictUtf8)]
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Counts digit strings in a file. This is the default, so
+    /// `count <ALGORITHM> ...` can be shortened to `<ALGORITHM> ...`.
+    Count(CliOptions),
+    /// Merges the counts files of several shards of a dataset
+    Merge(MergeOptions),
+    /// Finds every occurrence of a few digit strings in a file
+    Search(SearchOptions),
+    /// Writes a reproducible file of random digits for tests and benchmarks
+    Generate(GenerateOptions),
+    /// Writes the digits of pi, e or sqrt(2), like `3.243f6a88...`
+    GenerateConstant(GenerateConstantOptions),
+    /// Runs several algorithms over the same input and compares their
+    /// counts substring by substring
+    Verify(VerifyOptions),
+    /// Times algorithms and read modes over a file or generated digits
+    Bench(BenchOptions),
+    #[structopt(external_subcommand)]
+    Default(Vec<OsString>),
 }
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "basic")]
 struct CliOptions {
     #[structopt(name = "ALGORITHM")]
-    algorithm: String,
+    algorithm: Algorithm,
 
+    /// Input file, or `-` to read from stdin
     #[structopt(name = "FILE", parse(from_os_str))]
     file: PathBuf,
 
@@ -57,128 +76,1682 @@ struct CliOptions {
 
     #[structopt(short, long)]
     unmapped: bool,
+
+    /// Count the memory mapped file on this many threads (0 = all cores).
+    /// Only supported by the variant algorithms.
+    #[structopt(short, long)]
+    threads: Option<usize>,
+
+    /// Also write all counts with their substrings to `<FILE>_counts.txt`,
+    /// as needed by the merge subcommand.
+    #[structopt(short, long)]
+    counts: bool,
+
+    /// Digit symbols: binary, octal, decimal, hex, base36, or the symbols
+    /// themselves in order, like `ACGT`.
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    /// Format of the result file: text, json, ndjson, csv or tsv. All but
+    /// text write `(width, substring, count)` records after a metadata
+    /// header, to `<FILE>_result.<format>`.
+    #[structopt(short, long, default_value = "text")]
+    format: Format,
+
+    /// Path of the result file, instead of one next to FILE. Required when
+    /// reading from stdin. With `--counts`, the counts file is written next
+    /// to it.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Compression of the input: none, gzip, zstd, xz or bzip2. Detected
+    /// from the magic number if not given, `none` disables detection.
+    /// Compressed input is always read buffered.
+    #[structopt(short = "z", long)]
+    compression: Option<Compression>,
+
+    /// Fail instead of overwriting existing output files
+    #[structopt(short, long)]
+    no_clobber: bool,
+
+    /// Save the count to `<FILE>_checkpoint.bin` at most every this many
+    /// seconds, so `--resume` can continue it after a crash. Not supported
+    /// by the original algorithms or with threads.
+    #[structopt(long)]
+    checkpoint_interval: Option<u64>,
+
+    /// Continue from the checkpoint of an interrupted count with the same
+    /// options, or start from the beginning if there is none.
+    #[structopt(long)]
+    resume: bool,
+
+    #[structopt(flatten)]
+    select: SelectionOptions,
+
+    /// Also write the K most frequent substrings of each width, with all
+    /// ties of the last one, to `<FILE>_ranking.<format>`
+    #[structopt(long, value_name = "K")]
+    top: Option<usize>,
+
+    /// Like `--top`, for the least frequent substrings that occurred
+    #[structopt(long, value_name = "K")]
+    bottom: Option<usize>,
+
+    /// Also write how many substrings of each width never occurred, and
+    /// the smallest one, to `<FILE>_missing.<format>`
+    #[structopt(long)]
+    missing: bool,
+
+    /// Like `--missing`, also listing up to this many of the smallest
+    /// missing substrings of each width
+    #[structopt(long, value_name = "LIMIT")]
+    list_missing: Option<usize>,
+
+    /// Also write a chi-squared test against uniform digits, the entropy
+    /// and the extreme counts of each width to `<FILE>_stats.<format>`
+    #[structopt(long)]
+    stats: bool,
+
+    /// Also write the offset after the marker where each substring first
+    /// occurred, next to its count, to `<FILE>_first.<format>`. Needs a
+    /// variant algorithm with at most 128 bit packing
+    #[structopt(long)]
+    first_offsets: bool,
+}
+
+impl CliOptions {
+    fn is_stdin(&self) -> bool {
+        self.file == Path::new("-")
+    }
+
+    /// Whether the input can be memory mapped. Streams like stdin or named
+    /// pipes and compressed files are always read buffered.
+    fn is_mappable(&self) -> bool {
+        !self.unmapped
+            && !self.is_stdin()
+            && matches!(self.compression, None | Some(Compression::Plain))
+            && std::fs::metadata(&self.file).is_ok_and(|m| m.is_file())
+    }
+
+    /// Detects the compression of a regular input file up front, as that
+    /// decides how it is read. Streams are detected while reading.
+    fn detect_compression(&mut self) -> Result<()> {
+        if self.compression.is_some()
+            || self.is_stdin()
+            || !std::fs::metadata(&self.file).is_ok_and(|m| m.is_file())
+        {
+            return Ok(());
+        }
+        let (compression, _) =
+            decompress(open_input(&self.file)?, None).map_err(Error::io(&self.file))?;
+        println!("Compression: {}", compression);
+        self.compression = Some(compression);
+        Ok(())
+    }
+
+    fn result_path(&self) -> Result<PathBuf> {
+        match &self.output {
+            Some(output) => Ok(output.clone()),
+            None => output_path(&self.file, "result", self.format.extension()),
+        }
+    }
+
+    fn counts_path(&self) -> Result<Option<PathBuf>> {
+        if !self.counts {
+            return Ok(None);
+        }
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "counts", "txt").map(Some)
+    }
+
+    /// The requested rankings as `(width, order, k)`.
+    fn ranking_requests(&self) -> Vec<(usize, Order, usize)> {
+        let orders = [(Order::Top, self.top), (Order::Bottom, self.bottom)];
+        let orders = orders.iter().filter_map(|&(order, k)| Some((order, k?)));
+        orders
+            .flat_map(|(order, k)| (1..self.digit + 1).map(move |width| (width, order, k)))
+            .collect()
+    }
+
+    fn ranking_path(&self) -> Result<Option<PathBuf>> {
+        if self.top.is_none() && self.bottom.is_none() {
+            return Ok(None);
+        }
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "ranking", self.format.extension()).map(Some)
+    }
+
+    /// The number of missing substrings to list per width, if requested.
+    fn missing_limit(&self) -> Option<usize> {
+        match self.list_missing {
+            Some(limit) => Some(limit),
+            None if self.missing => Some(1),
+            None => None,
+        }
+    }
+
+    fn missing_path(&self) -> Result<Option<PathBuf>> {
+        if self.missing_limit().is_none() {
+            return Ok(None);
+        }
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "missing", self.format.extension()).map(Some)
+    }
+
+    fn stats_path(&self) -> Result<Option<PathBuf>> {
+        if !self.stats {
+            return Ok(None);
+        }
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "stats", self.format.extension()).map(Some)
+    }
+
+    fn first_offsets_path(&self) -> Result<Option<PathBuf>> {
+        if !self.first_offsets {
+            return Ok(None);
+        }
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "first", self.format.extension()).map(Some)
+    }
+
+    fn uses_checkpoints(&self) -> bool {
+        self.checkpoint_interval.is_some() || self.resume
+    }
+
+    fn checkpoint_path(&self) -> Result<PathBuf> {
+        let base = self.output.as_ref().unwrap_or(&self.file);
+        output_path(base, "checkpoint", "bin")
+    }
+
+    fn checkpoint_info(&self, offset: u64) -> Result<CheckpointInfo> {
+        Ok(CheckpointInfo {
+            algorithm: self.algorithm.to_string(),
+            digits: self.digit,
+            alphabet: self.alphabet.symbols().to_vec(),
+            selection: self.select.selection()?,
+            offset,
+        })
+    }
+
+    /// Rejects invocations that would only fail after counting.
+    fn validate(&self) -> Result<()> {
+        if self.is_stdin() && self.output.is_none() {
+            return Err(Error::InvalidArgs(
+                "reading from stdin needs an explicit --output path".to_owned(),
+            ));
+        }
+        self.algorithm.check_alphabet(&self.alphabet)?;
+        self.algorithm.check_width(self.digit, &self.alphabet)?;
+        self.select.selection()?;
+        if self.uses_checkpoints() {
+            if matches!(self.algorithm, Algorithm::Original | Algorithm::OriginalHex) {
+                return Err(Error::InvalidArgs(format!(
+                    "{} does not support checkpoints",
+                    self.algorithm
+                )));
+            }
+            if self.threads.is_some() {
+                return Err(Error::InvalidArgs(
+                    "checkpoints are not supported with --threads".to_owned(),
+                ));
+            }
+            self.checkpoint_path()?;
+        }
+        let result_path = self.result_path()?;
+        check_clobber(&result_path, self.no_clobber)?;
+        if let Some(counts_path) = self.counts_path()? {
+            check_clobber(&counts_path, self.no_clobber)?;
+        }
+        if let Some(ranking_path) = self.ranking_path()? {
+            check_clobber(&ranking_path, self.no_clobber)?;
+        }
+        if let Some(missing_path) = self.missing_path()? {
+            check_clobber(&missing_path, self.no_clobber)?;
+        }
+        if let Some(stats_path) = self.stats_path()? {
+            check_clobber(&stats_path, self.no_clobber)?;
+        }
+        if let Some(first_offsets_path) = self.first_offsets_path()? {
+            if self.uses_checkpoints() {
+                return Err(Error::InvalidArgs(
+                    "--first-offsets does not support checkpoints".to_owned(),
+                ));
+            }
+            check_clobber(&first_offsets_path, self.no_clobber)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which bytes of the input are used, shared by all subcommands reading one.
+#[derive(StructOpt, Debug)]
+struct SelectionOptions {
+    /// Use only the input after the first occurrence of this byte string,
+    /// which skips the `3.` of `3.14159...`. Understands `\n`, `\r`, `\t`,
+    /// `\\` and `\xHH`. Nothing is used if it does not occur.
+    #[structopt(long, default_value = ".")]
+    marker: String,
+
+    /// Start at the beginning of the input, without a marker
+    #[structopt(long, conflicts_with = "marker")]
+    no_marker: bool,
+
+    /// Skip this many bytes after the marker
+    #[structopt(long, conflicts_with = "range")]
+    skip: Option<u64>,
+
+    /// Use only the bytes `START..END` after the marker. Either side can
+    /// be left out, like `1000..`.
+    #[structopt(long)]
+    range: Option<ByteRange>,
+
+    /// Stop after this many digits
+    #[structopt(long)]
+    first: Option<u64>,
+}
+
+impl SelectionOptions {
+    fn selection(&self) -> Result<Selection> {
+        let range = match self.skip {
+            Some(skip) => ByteRange {
+                start: skip,
+                end: None,
+            },
+            None => self.range.unwrap_or_default(),
+        };
+        let marker = if self.no_marker {
+            None
+        } else {
+            parse_marker(&self.marker).map_err(Error::InvalidArgs)?
+        };
+        Ok(Selection {
+            marker,
+            range,
+            max_digits: self.first,
+        })
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct MergeOptions {
+    /// Path of the merged counts file. The summary is written next to it.
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Format of the summary, see `count --help`
+    #[structopt(short, long, default_value = "text")]
+    format: Format,
+
+    /// Counts files written with `--counts`
+    #[structopt(name = "FILES", parse(from_os_str), required = true)]
+    files: Vec<PathBuf>,
+
+    /// Fail instead of overwriting existing output files
+    #[structopt(short, long)]
+    no_clobber: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct SearchOptions {
+    /// Input file, or `-` to read from stdin
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+
+    /// Digit strings to find, like `0314`. Each match is reported with the
+    /// offset of its first digit after the marker, also with `--range`, like
+    /// `count --first-offsets` does.
+    #[structopt(name = "PATTERNS", required = true)]
+    patterns: Vec<String>,
+
+    /// Digit symbols, see `count --help`
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    /// Format of the matches file: text, json, ndjson, csv or tsv
+    #[structopt(short, long, default_value = "text")]
+    format: Format,
+
+    /// Path of the matches file, instead of `<FILE>_search.<format>` next
+    /// to FILE. Required when reading from stdin.
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Compression of the input, see `count --help`
+    #[structopt(short = "z", long)]
+    compression: Option<Compression>,
+
+    #[structopt(flatten)]
+    select: SelectionOptions,
+
+    /// Fail instead of overwriting existing output files
+    #[structopt(short, long)]
+    no_clobber: bool,
+}
+
+impl SearchOptions {
+    fn output_path(&self) -> Result<PathBuf> {
+        match &self.output {
+            Some(output) => Ok(output.clone()),
+            None if self.file == Path::new("-") => Err(Error::InvalidArgs(
+                "reading from stdin needs an explicit --output path".to_owned(),
+            )),
+            None => output_path(&self.file, "search", self.format.extension()),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct GenerateOptions {
+    /// Output file, or `-` to write to stdout
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+
+    /// Number of random bytes after the prefix, with an optional K, M, G or
+    /// T suffix
+    #[structopt(short, long, default_value = "1M")]
+    size: ByteSize,
+
+    /// Seed of the random generator. The same options and seed always give
+    /// the same file.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// Digit symbols, see `count --help`
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    /// Bytes written before the random ones, with the escapes of
+    /// `count --marker`
+    #[structopt(long, default_value = "1.")]
+    prefix: String,
+
+    /// Probability of each byte being a noise byte, which is never a digit.
+    /// Only used with geometric run lengths.
+    #[structopt(long, default_value = "0.01")]
+    noise: f64,
+
+    /// Lengths of the runs of digits between noise bytes: `geometric`,
+    /// `fixed:N` or `uniform:MIN..MAX`. Other than geometric runs are
+    /// separated by single noise bytes.
+    #[structopt(long, default_value = "geometric")]
+    runs: RunLengths,
+
+    /// Fail instead of overwriting an existing output file
+    #[structopt(short, long)]
+    no_clobber: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct GenerateConstantOptions {
+    /// The constant: pi, e or sqrt2
+    #[structopt(name = "CONSTANT")]
+    constant: Constant,
+
+    /// Output file, or `-` to write to stdout
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+
+    /// Number of digits after the point. Time grows with its square.
+    #[structopt(short, long, default_value = "100000")]
+    digits: usize,
+
+    /// Digit symbols, see `count --help`
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    /// Fail instead of overwriting an existing output file
+    #[structopt(short, long)]
+    no_clobber: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct VerifyOptions {
+    /// Input file, or `-` to read from stdin
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: PathBuf,
+
+    #[structopt(name = "DIGIT")]
+    digit: usize,
+
+    /// Algorithms to run, separated by commas. The others are compared with
+    /// the first one. Defaults to all, leaving out `original` for alphabets
+    /// other than decimal, as it only counts decimal digits.
+    #[structopt(short = "A", long, use_delimiter = true)]
+    algorithms: Vec<Algorithm>,
+
+    /// Also run the standalone counter of `single_file.rs`, which only
+    /// counts hex digits
+    #[structopt(long)]
+    single_file: bool,
+
+    /// Number of differing substrings to list for each algorithm
+    #[structopt(long, default_value = "10")]
+    max_differences: usize,
+
+    /// Digit symbols, see `count --help`
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    /// Compression of the input, see `count --help`
+    #[structopt(short = "z", long)]
+    compression: Option<Compression>,
+
+    #[structopt(flatten)]
+    select: SelectionOptions,
+}
+
+#[derive(StructOpt, Debug)]
+struct BenchOptions {
+    #[structopt(name = "DIGIT")]
+    digit: usize,
+
+    /// Input file. Without one, random digits are generated into a
+    /// temporary file, see `generate --help`.
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file: Option<PathBuf>,
+
+    /// Size of the generated input, with an optional K, M, G or T suffix.
+    /// Ignored with FILE.
+    #[structopt(short, long, default_value = "64M")]
+    size: ByteSize,
+
+    /// Seed of the generated input. Ignored with FILE.
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+
+    /// Algorithms to time, separated by commas
+    #[structopt(
+        short = "A",
+        long,
+        use_delimiter = true,
+        default_value = "variant-1,variant-2,variant-3,variant-4"
+    )]
+    algorithms: Vec<Algorithm>,
+
+    /// Ways to read the input, separated by commas: `mmap` and `unmapped`,
+    /// which reads through a buffer of each of `--capacities`
+    #[structopt(long, use_delimiter = true, default_value = "mmap,unmapped")]
+    modes: Vec<ReadMode>,
+
+    /// Buffer capacities of unmapped reads, separated by commas
+    #[structopt(long, use_delimiter = true, default_value = "8192")]
+    capacities: Vec<usize>,
+
+    /// Number of times to run each configuration
+    #[structopt(short, long, default_value = "3")]
+    repetitions: usize,
+
+    /// Digit symbols, see `count --help`
+    #[structopt(short, long, default_value = "hex")]
+    alphabet: Alphabet,
+
+    #[structopt(flatten)]
+    select: SelectionOptions,
+
+    /// Format of the results: text, json, ndjson, csv or tsv
+    #[structopt(short, long, default_value = "text")]
+    format: Format,
+
+    /// Write the results to this file instead of stdout
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Fail instead of overwriting an existing output file
+    #[structopt(short, long)]
+    no_clobber: bool,
+}
+
+impl BenchOptions {
+    fn validate(&self) -> Result<()> {
+        for algorithm in &self.algorithms {
+            algorithm.check_alphabet(&self.alphabet)?;
+            algorithm.check_width(self.digit, &self.alphabet)?;
+        }
+        if self.repetitions == 0 {
+            return Err(Error::InvalidArgs(
+                "--repetitions must be at least 1".to_owned(),
+            ));
+        }
+        if self.capacities.contains(&0) {
+            return Err(Error::InvalidArgs(
+                "--capacities must be at least 1".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The read configurations to time, as mode and buffer capacity.
+    fn configurations(&self) -> Vec<(ReadMode, Option<usize>)> {
+        let mut configurations = Vec::new();
+        for &mode in &self.modes {
+            match mode {
+                ReadMode::Mmap => configurations.push((mode, None)),
+                ReadMode::Unmapped => {
+                    configurations.extend(self.capacities.iter().map(|&c| (mode, Some(c))))
+                }
+            }
+        }
+        configurations
+    }
+}
+
+/// How the bench subcommand reads its input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadMode {
+    Mmap,
+    Unmapped,
+}
+
+impl ReadMode {
+    const ALL: &'static [ReadMode] = &[ReadMode::Mmap, ReadMode::Unmapped];
+
+    fn name(self) -> &'static str {
+        match self {
+            ReadMode::Mmap => "mmap",
+            ReadMode::Unmapped => "unmapped",
+        }
+    }
+}
+
+impl std::str::FromStr for ReadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ReadMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| format!("unknown read mode {:?}, expected mmap or unmapped", s))
+    }
+}
+
+impl VerifyOptions {
+    fn algorithms(&self) -> Vec<Algorithm> {
+        if !self.algorithms.is_empty() {
+            return self.algorithms.clone();
+        }
+        let decimal = self.alphabet.symbols() == Alphabet::decimal().symbols();
+        let all = Algorithm::ALL.iter().copied();
+        all.filter(|&a| a != Algorithm::Auto && (decimal || a != Algorithm::Original))
+            .collect()
+    }
+
+    fn validate(&self) -> Result<()> {
+        for algorithm in self.algorithms() {
+            algorithm.check_alphabet(&self.alphabet)?;
+            algorithm.check_width(self.digit, &self.alphabet)?;
+        }
+        if self.single_file {
+            if self.alphabet.symbols() != Alphabet::hex().symbols() {
+                return Err(Error::InvalidArgs(
+                    "--single-file only counts hex digits".to_owned(),
+                ));
+            }
+            if self.digit > 16 {
+                return Err(Error::UnsupportedWidth {
+                    digits: self.digit,
+                    reason: "--single-file counts at most 16 digits".to_owned(),
+                });
+            }
+        }
+        if self.algorithms().len() + usize::from(self.single_file) < 2 {
+            return Err(Error::InvalidArgs(
+                "verifying needs at least two algorithms".to_owned(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        std::process::exit(error.exit_code());
+    }
+}
+
+/// Parses the command line, exiting right away only for `--help` and
+/// `--version`.
+fn parse_args<T: StructOpt>(args: impl IntoIterator<Item = OsString>) -> Result<T> {
+    use structopt::clap::ErrorKind;
+    match T::from_iter_safe(args) {
+        Ok(opt) => Ok(opt),
+        Err(error)
+            if matches!(
+                error.kind,
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+            ) =>
+        {
+            error.exit()
+        }
+        Err(error) => {
+            // NB: clap already starts its message with `error:`
+            let message = match error.message.split_once("error:") {
+                Some((_, message)) => message.trim_start().to_owned(),
+                None => error.message,
+            };
+            Err(Error::InvalidArgs(message))
+        }
+    }
+}
+
+fn run() -> Result<()> {
     if false {
-        single_file::main();
+        single_file::main()?;
     }
 
-    let opt = CliOptions::from_args();
+    let mut opt = match parse_args(std::env::args_os())? {
+        Command::Count(opt) => opt,
+        Command::Merge(opt) => return merge_main(opt),
+        Command::Search(opt) => return search_main(opt),
+        Command::Generate(opt) => return generate_main(opt),
+        Command::GenerateConstant(opt) => return generate_constant_main(opt),
+        Command::Verify(opt) => return verify_main(opt),
+        Command::Bench(opt) => return bench_main(opt),
+        Command::Default(args) => {
+            parse_args::<CliOptions>(std::iter::once("count-digits".into()).chain(args))?
+        }
+    };
+    opt.validate()?;
+    opt.detect_compression()?;
+    resolve_auto(&mut opt)?;
     println!("{:#?}", opt);
 
-    match &opt.algorithm[..] {
-        "original" => generic_main::<Original<original::StdNumeric>>(opt),
-        "original-hex" => generic_main::<Original<original::HexDigit>>(opt),
-        "variant-1" => generic_main::<Variant<variant::EarlyCount, variant::HashMapCounter>>(opt),
-        "variant-2" => generic_main::<Variant<variant::LateCount, variant::HashMapCounter>>(opt),
-        "variant-3" => generic_main::<Variant<variant::EarlyCount, variant::VecCounter>>(opt),
-        "variant-4" => generic_main::<Variant<variant::LateCount, variant::VecCounter>>(opt),
-        other => {
-            panic!("Unsupported algorithm {}\n{}", other, ERRMSG);
+    match opt.algorithm {
+        Algorithm::Original => generic_main::<Original<original::StdNumeric>>(opt),
+        Algorithm::OriginalHex => generic_main::<Original<original::AlphabetDigit>>(opt),
+        Algorithm::Variant1 => variant_main::<
+            variant::EarlyCount,
+            variant::HashMapCounter,
+            variant::HashMapCounter<u128>,
+        >(opt),
+        Algorithm::Variant2 => {
+            variant_main::<variant::LateCount, variant::HashMapCounter, variant::HashMapCounter<u128>>(
+                opt,
+            )
         }
+        Algorithm::Variant3 => {
+            variant_main::<variant::EarlyCount, variant::VecCounter, variant::VecCounter<u128>>(opt)
+        }
+        Algorithm::Variant4 => {
+            variant_main::<variant::LateCount, variant::VecCounter, variant::VecCounter<u128>>(opt)
+        }
+        Algorithm::Variant4U32 => variant_main::<
+            variant::LateCount,
+            variant::CompactVecCounter<u64, u32>,
+            variant::CompactVecCounter<u128, u32>,
+        >(opt),
+        Algorithm::Variant4U16 => variant_main::<
+            variant::LateCount,
+            variant::CompactVecCounter<u64, u16>,
+            variant::CompactVecCounter<u128, u16>,
+        >(opt),
+        Algorithm::Auto => unreachable!("resolved above"),
     }
 }
 
-fn main_loop<T: Process>(
-    imp: &mut T,
-    mut iter: impl Iterator<Item = u8>,
-    mut count_callback: impl FnMut(&Instant),
-) -> Instant {
-    for byte in &mut iter {
-        if byte == b'.' {
-            break;
+/// Replaces `auto` by the algorithm it chooses, or by the one of the
+/// checkpoint that is resumed.
+fn resolve_auto(opt: &mut CliOptions) -> Result<()> {
+    if opt.algorithm != Algorithm::Auto {
+        return Ok(());
+    }
+    if opt.resume {
+        let path = opt.checkpoint_path()?;
+        if let Ok(file) = File::open(&path) {
+            let info =
+                CheckpointInfo::read_from(&mut BufReader::new(file)).map_err(Error::io(&path))?;
+            if let Ok(algorithm) = info.algorithm.parse() {
+                println!("Auto: resuming with {} of the checkpoint", algorithm);
+                opt.algorithm = algorithm;
+                return Ok(());
+            }
         }
     }
+
+    // NB: Compressed inputs hold more digits than their size.
+    let input_size =
+        if opt.is_stdin() || !matches!(opt.compression, None | Some(Compression::Plain)) {
+            None
+        } else {
+            std::fs::metadata(&opt.file).ok().map(|m| m.len())
+        };
+    let threads = match opt.threads {
+        Some(0) if opt.is_mappable() => parallel::default_threads(),
+        Some(threads) if opt.is_mappable() => threads,
+        _ => 1,
+    };
+    let copies = if opt.first_offsets { 2 } else { 1 } * threads;
+    let choice = Algorithm::choose(
+        opt.digit,
+        &opt.alphabet,
+        input_size,
+        available_memory(),
+        copies,
+    );
+    println!("Auto: {}", choice);
+    opt.algorithm = choice.algorithm;
+    opt.algorithm.check_width(opt.digit, &opt.alphabet)
+}
+
+fn variant_main<T, U, W>(opt: CliOptions) -> Result<()>
+where
+    T: CountStrategy + Send,
+    U: for<'a> CounterStorage<'a, u64> + Send,
+    W: for<'a> CounterStorage<'a, u128> + Send,
+{
+    let packing = Packing::for_digits(opt.digit, &opt.alphabet);
+    println!("Packing: {:?}", packing);
+    println!("SIMD: {}", SimdLevel::for_alphabet(&opt.alphabet));
+    match packing {
+        Packing::U64 => packed_main::<T, U, u64>(opt),
+        Packing::U128 => packed_main::<T, W, u128>(opt),
+        Packing::Wide => generic_main::<WideVariant>(opt),
+    }
+}
+
+fn packed_main<T, U, N>(opt: CliOptions) -> Result<()>
+where
+    T: CountStrategy + Send,
+    N: Number,
+    U: for<'a> CounterStorage<'a, N> + Send,
+{
+    match opt.threads {
+        Some(threads) if opt.is_mappable() => parallel_main::<T, U, N>(opt, threads),
+        _ => generic_main::<Variant<T, U, N>>(opt),
+    }
+}
+
+/// Returns the next buffered bytes of `input`, retrying interrupted reads.
+fn fill_buf<'a>(input: &'a mut impl BufRead, path: &Path) -> Result<&'a [u8]> {
+    loop {
+        match input.fill_buf() {
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::io(path)(e)),
+        }
+    }
+    // NB: Only returns the buffer filled above, the borrow checker does
+    // not allow returning it from the loop.
+    input.fill_buf().map_err(Error::io(path))
+}
+
+/// Feeds the bytes selected by `selector` to `imp`.
+///
+/// Bytes are passed on a buffer at a time, cut at every multiple of
+/// `INTERVAL` bytes after the marker to call `interval_callback` with the
+/// offset so far. Returns the final offset along with the start time.
+fn main_loop<T: Process>(
+    imp: &mut T,
+    mut input: impl BufRead,
+    path: &Path,
+    mut selector: Selector,
+    mut interval_callback: impl FnMut(&mut T, Counter, &Instant) -> Result<()>,
+) -> Result<(Instant, Counter)> {
     let mut hex_count = [0u64; 16];
     let now = std::time::Instant::now();
-    for byte in iter {
-        imp.on_byte(byte);
-        count_callback(&now);
-        if DEBUG_HEX_HISTOGRAM {
-            let n = if b'0' <= byte && byte <= b'9' {
-                byte - b'0'
-            } else if b'a' <= byte && byte <= b'f' {
-                byte - b'a' + 10
-            } else if b'A' <= byte && byte <= b'F' {
-                byte - b'A' + 10
-            } else {
-                continue;
-            };
-            hex_count[n as usize] += 1;
+    loop {
+        let buf = fill_buf(&mut input, path)?;
+        if buf.is_empty() {
+            break;
+        }
+        let to_interval = INTERVAL - selector.position() % INTERVAL;
+        let buf = &buf[..buf.len().min(to_interval as usize)];
+        let (len, counted) = match selector.next(buf) {
+            Step::Skip(len) => (len, false),
+            Step::Count(len) => {
+                let chunk = &buf[..len];
+                imp.on_chunk(chunk);
+                if DEBUG_HEX_HISTOGRAM {
+                    for &byte in chunk {
+                        let n = match byte {
+                            b'0'..=b'9' => byte - b'0',
+                            b'a'..=b'f' => byte - b'a' + 10,
+                            b'A'..=b'F' => byte - b'A' + 10,
+                            _ => continue,
+                        };
+                        hex_count[n as usize] += 1;
+                    }
+                }
+                (len, true)
+            }
+            Step::Done => break,
+        };
+        input.consume(len);
+        if counted && len as Counter == to_interval {
+            interval_callback(imp, selector.position(), &now)?;
         }
     }
     if DEBUG_HEX_HISTOGRAM {
         println!("debug hex histogram: = {:?}", hex_count);
     }
-    now
+    Ok((now, selector.position()))
 }
 
-fn generic_main<T: Process>(opt: CliOptions) {
-    let path = opt.file;
-    let digit = opt.digit;
+fn generic_main<T: Process>(opt: CliOptions) -> Result<()> {
+    if opt.threads.is_some() {
+        println!("Threads are only supported for memory mapped reads with a variant algorithm");
+    }
 
-    let mut imp = T::new(digit);
+    let mut imp = T::new(opt.digit, &opt.alphabet);
+    if opt.first_offsets && !imp.record_first_offsets(opt.select.selection()?.range.start) {
+        return Err(Error::InvalidArgs(format!(
+            "{} with {} digits does not record first offsets",
+            opt.algorithm, opt.digit
+        )));
+    }
+    let offset = if opt.resume {
+        resume_checkpoint(&opt, &mut imp)?
+    } else {
+        0
+    };
+    let mut selector = Selector::new(&opt.select.selection()?, &opt.alphabet);
+    selector.resume_at(offset);
 
-    let mut cnt: Counter = 0;
-    let mut cntp: Counter = 0;
-    let do_count = |now: &Instant| {
-        cnt += 1;
-        if cnt == INTERVAL {
-            cntp += 1;
-            println!(
-                "Digits: {}, Time: {}",
-                cntp * INTERVAL,
-                now.elapsed().as_secs_f64()
-            );
-            cnt = 0;
+    let mut last_checkpoint = Instant::now();
+    let on_interval = |imp: &mut T, digits: Counter, now: &Instant| {
+        println!("Digits: {}, Time: {}", digits, now.elapsed().as_secs_f64());
+        if let Some(interval) = opt.checkpoint_interval {
+            if last_checkpoint.elapsed().as_secs() >= interval {
+                save_checkpoint(&opt, imp, digits)?;
+                last_checkpoint = Instant::now();
+            }
         }
+        Ok(())
     };
 
-    let filestream = match std::fs::File::open(&path) {
-        Ok(file) => file,
-        Err(error) => panic!("{}\n{}", error, ERRMSG),
-    };
-    let now = if !opt.unmapped {
+    let (now, digits) = if opt.is_mappable() {
         println!("Memory mapped read");
-        let memmap = unsafe { memmap::Mmap::map(&filestream).unwrap() };
-        let iter = memmap.iter().copied();
-        main_loop(&mut imp, iter, do_count)
+        let filestream = open_input(&opt.file)?;
+        let memmap = unsafe { memmap::Mmap::map(&filestream) }.map_err(Error::io(&opt.file))?;
+        main_loop(&mut imp, &memmap[..], &opt.file, selector, on_interval)?
     } else {
         println!("Buffered read");
         let capacity = opt.capacity.unwrap_or_else(|| {
             println!("Using default capacity {}", STD_CAPACITY);
             STD_CAPACITY
         });
-        let bufstream = std::io::BufReader::with_capacity(capacity, filestream);
-        let iter = bufstream.bytes().map(|b| b.unwrap());
-        main_loop(&mut imp, iter, do_count)
+        let input: Box<dyn Read> = if opt.is_stdin() {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(open_input(&opt.file)?)
+        };
+        let (compression, input) =
+            decompress(input, opt.compression).map_err(Error::io(&opt.file))?;
+        if opt.compression.is_none() {
+            println!("Compression: {}", compression);
+        }
+        let bufstream = BufReader::with_capacity(capacity, input);
+        main_loop(&mut imp, bufstream, &opt.file, selector, on_interval)?
     };
+    if digits < offset {
+        return Err(Error::InvalidArgs(format!(
+            "the input is shorter than the checkpoint offset {}",
+            offset
+        )));
+    }
 
     println!(
         "Digits: {}, Eof Time: {}",
-        cntp * INTERVAL + cnt,
+        digits,
         now.elapsed().as_secs_f64()
     );
     imp.finalize();
     println!(
         "Digits: {}, Final Time: {}",
-        cntp * INTERVAL + cnt,
+        digits,
         now.elapsed().as_secs_f64()
     );
 
-    let outpath = format!(
-        "{}/{}_result.txt",
-        path.parent().unwrap().display(),
-        path.file_stem().unwrap().to_str().unwrap()
+    let (count, reports) = into_count_and_reports(&opt, imp);
+    finish_count(&opt, now.elapsed(), &count, &reports)?;
+
+    if opt.uses_checkpoints() {
+        let checkpoint_path = opt.checkpoint_path()?;
+        if checkpoint_path.exists() {
+            std::fs::remove_file(&checkpoint_path).map_err(Error::io(&checkpoint_path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Atomically replaces the checkpoint with the current state of `imp`.
+fn save_checkpoint<T: Process>(opt: &CliOptions, imp: &mut T, offset: u64) -> Result<()> {
+    let path = opt.checkpoint_path()?;
+    let tmp_path = path.with_extension("tmp");
+    let file = File::create(&tmp_path).map_err(Error::io(&tmp_path))?;
+    let mut w = BufWriter::new(file);
+    opt.checkpoint_info(offset)?
+        .write_to(&mut w)
+        .and_then(|_| imp.save_state(&mut w))
+        .and_then(|_| w.flush())
+        .and_then(|_| w.get_ref().sync_all())
+        .map_err(Error::io(&tmp_path))?;
+    std::fs::rename(&tmp_path, &path).map_err(Error::io(&path))?;
+    println!("Checkpoint at {}: {}", offset, path.display());
+    Ok(())
+}
+
+/// Loads the checkpoint into the new `imp`, returning its offset.
+fn resume_checkpoint<T: Process>(opt: &CliOptions, imp: &mut T) -> Result<u64> {
+    let path = opt.checkpoint_path()?;
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            println!("No checkpoint at {}, starting over", path.display());
+            return Ok(0);
+        }
+        Err(error) => return Err(Error::io(&path)(error)),
+    };
+    let mut r = BufReader::new(file);
+    let info = CheckpointInfo::read_from(&mut r).map_err(Error::io(&path))?;
+    if info != opt.checkpoint_info(info.offset)? {
+        return Err(Error::InvalidArgs(format!(
+            "{} is a checkpoint of a different count: {} of {} digits",
+            path.display(),
+            info.algorithm,
+            info.digits
+        )));
+    }
+    imp.load_state(&mut r).map_err(Error::io(&path))?;
+    println!("Resuming at {} from {}", info.offset, path.display());
+    Ok(info.offset)
+}
+
+fn parallel_main<T, U, N>(opt: CliOptions, threads: usize) -> Result<()>
+where
+    T: CountStrategy + Send,
+    N: Number,
+    U: for<'a> CounterStorage<'a, N> + Send,
+{
+    let threads = if threads == 0 {
+        parallel::default_threads()
+    } else {
+        threads
+    };
+
+    let filestream = open_input(&opt.file)?;
+    println!("Memory mapped read on {} threads", threads);
+    let memmap = unsafe { memmap::Mmap::map(&filestream) }.map_err(Error::io(&opt.file))?;
+    let selection = opt.select.selection()?;
+    let bytes = selection.select(&memmap, &opt.alphabet);
+    let first_offset = Some(selection.range.start).filter(|_| opt.first_offsets);
+
+    let now = std::time::Instant::now();
+    let imp =
+        parallel::count_chunked::<T, U, N>(bytes, opt.digit, &opt.alphabet, threads, first_offset);
+    println!(
+        "Digits: {}, Final Time: {}",
+        bytes.len(),
+        now.elapsed().as_secs_f64()
     );
+
+    let elapsed = now.elapsed();
+    let (count, reports) = into_count_and_reports(&opt, imp);
+    finish_count(&opt, elapsed, &count, &reports)
+}
+
+fn open_input(path: &Path) -> Result<File> {
+    File::open(path).map_err(Error::io(path))
+}
+
+fn check_clobber(path: &Path, no_clobber: bool) -> Result<()> {
+    if no_clobber && path.exists() {
+        return Err(Error::OutputExists(path.to_owned()));
+    }
+    Ok(())
+}
+
+fn create_output(path: &Path, no_clobber: bool) -> Result<BufWriter<File>> {
+    check_clobber(path, no_clobber)?;
+    let file = File::create(path).map_err(Error::io(path))?;
+    Ok(BufWriter::new(file))
+}
+
+/// The reports requested besides the counts.
+struct Reports {
+    rankings: Vec<Ranking>,
+    missing: Vec<Missing>,
+    stats: Vec<Stats>,
+    first_offsets: Option<FirstOffsets>,
+}
+
+/// Returns the counts of the finalized `imp`, and the requested rankings,
+/// missing substrings and statistics.
+///
+/// Engines report on themselves where they can, which avoids going through
+/// all substrings of all widths in the counts.
+fn into_count_and_reports<T: Process>(opt: &CliOptions, mut imp: T) -> (Counts, Reports) {
+    let requests = opt.ranking_requests();
+    let ranked = requests
+        .iter()
+        .map(|&(width, order, k)| imp.ranking(width, order, k))
+        .collect::<Vec<_>>();
+    let widths = 1..opt.digit + 1;
+    let missing = match opt.missing_limit() {
+        Some(limit) => widths
+            .clone()
+            .map(|width| imp.missing(width, limit))
+            .collect(),
+        None => Vec::new(),
+    };
+    let stats = if opt.stats {
+        widths.map(|width| imp.stats(width)).collect()
+    } else {
+        Vec::new()
+    };
+    let first_offsets = imp.first_offsets();
     let count = imp.into_count();
+    let rankings = requests
+        .iter()
+        .zip(ranked)
+        .map(|(&(width, order, k), ranking)| {
+            ranking.unwrap_or_else(|| Ranking::from_counts(&count, width, order, k))
+        })
+        .collect();
+    let missing = missing
+        .into_iter()
+        .enumerate()
+        .map(|(i, missing)| {
+            missing.unwrap_or_else(|| {
+                let limit = opt.missing_limit().unwrap();
+                Missing::from_counts(&count, &opt.alphabet, i + 1, limit)
+            })
+        })
+        .collect();
+    let stats = stats
+        .into_iter()
+        .enumerate()
+        .map(|(i, stats)| {
+            stats.unwrap_or_else(|| Stats::from_counts(&count, opt.alphabet.radix(), i + 1))
+        })
+        .collect();
+    let reports = Reports {
+        rankings,
+        missing,
+        stats,
+        first_offsets,
+    };
+    (count, reports)
+}
+
+fn finish_count(
+    opt: &CliOptions,
+    elapsed: Duration,
+    count: &Counts,
+    reports: &Reports,
+) -> Result<()> {
+    let file_size = if opt.is_stdin() {
+        None
+    } else {
+        std::fs::metadata(&opt.file).ok().map(|m| m.len())
+    };
+    let report = Report {
+        input: opt.file.display().to_string(),
+        file_size,
+        algorithm: opt.algorithm.to_string(),
+        elapsed,
+        counts: count,
+        rankings: &reports.rankings,
+        missing: &reports.missing,
+        stats: &reports.stats,
+        first_offsets: reports.first_offsets.as_ref(),
+    };
+    if let Some(counts_path) = opt.counts_path()? {
+        write_counts(&counts_path, count, opt.no_clobber)?;
+    }
+    if let Some(ranking_path) = opt.ranking_path()? {
+        println!("Ranking path: {}", ranking_path.display());
+        let mut output = create_output(&ranking_path, opt.no_clobber)?;
+        report
+            .write_rankings_to(opt.format, &mut output)
+            .and_then(|_| output.flush())
+            .map_err(Error::io(&ranking_path))?;
+    }
+    if let Some(missing_path) = opt.missing_path()? {
+        println!("Missing path: {}", missing_path.display());
+        let mut output = create_output(&missing_path, opt.no_clobber)?;
+        report
+            .write_missing_to(opt.format, &mut output)
+            .and_then(|_| output.flush())
+            .map_err(Error::io(&missing_path))?;
+    }
+    if let Some(stats_path) = opt.stats_path()? {
+        println!("Stats path: {}", stats_path.display());
+        let mut output = create_output(&stats_path, opt.no_clobber)?;
+        report
+            .write_stats_to(opt.format, &mut output)
+            .and_then(|_| output.flush())
+            .map_err(Error::io(&stats_path))?;
+    }
+    if let Some(first_offsets_path) = opt.first_offsets_path()? {
+        println!("First offsets path: {}", first_offsets_path.display());
+        let mut output = create_output(&first_offsets_path, opt.no_clobber)?;
+        report
+            .write_first_offsets_to(opt.format, &mut output)
+            .and_then(|_| output.flush())
+            .map_err(Error::io(&first_offsets_path))?;
+    }
+    write_result(&opt.result_path()?, &report, opt.format, opt.no_clobber)
+}
+
+fn merge_main(opt: MergeOptions) -> Result<()> {
+    let now = Instant::now();
+    let outpath = output_path(&opt.output, "result", opt.format.extension())?;
+    check_clobber(&opt.output, opt.no_clobber)?;
+    check_clobber(&outpath, opt.no_clobber)?;
+
+    let mut merged: Option<Counts> = None;
+    for path in &opt.files {
+        println!("Reading counts: {}", path.display());
+        let file = open_input(path)?;
+        let count = Counts::read_from(BufReader::new(file)).map_err(Error::io(path))?;
+        match &mut merged {
+            Some(merged) => merged
+                .merge(&count)
+                .map_err(|e| Error::InvalidArgs(format!("{}: {}", path.display(), e)))?,
+            None => merged = Some(count),
+        }
+    }
+    // NB: structopt requires at least one file
+    let merged = merged.unwrap();
+
+    write_counts(&opt.output, &merged, opt.no_clobber)?;
+    let inputs = opt.files.iter().map(|p| p.display().to_string());
+    let report = Report {
+        input: inputs.collect::<Vec<_>>().join(","),
+        file_size: None,
+        algorithm: "merge".to_owned(),
+        elapsed: now.elapsed(),
+        counts: &merged,
+        rankings: &[],
+        missing: &[],
+        stats: &[],
+        first_offsets: None,
+    };
+    write_result(&outpath, &report, opt.format, opt.no_clobber)
+}
+
+fn search_main(opt: SearchOptions) -> Result<()> {
+    let patterns = opt.patterns.iter().map(|p| p.as_bytes().to_vec());
+    let patterns = patterns.collect::<Vec<_>>();
+    let width = patterns.iter().map(Vec::len).max().unwrap_or(0);
+    // NB: Patterns too long for a u128 are rejected by the searcher.
+    match Packing::for_digits(width, &opt.alphabet) {
+        Packing::U64 => search_packed(&opt, Searcher::<u64>::new(&patterns, &opt.alphabet)?),
+        _ => search_packed(&opt, Searcher::<u128>::new(&patterns, &opt.alphabet)?),
+    }
+}
+
+fn search_packed<N: Number>(opt: &SearchOptions, mut searcher: Searcher<N>) -> Result<()> {
+    let outpath = opt.output_path()?;
+    let selection = opt.select.selection()?;
+    let mut selector = Selector::new(&selection, &opt.alphabet);
+    searcher.start_at(selection.range.start);
+    check_clobber(&outpath, opt.no_clobber)?;
+
+    let input: Box<dyn Read> = if opt.file == Path::new("-") {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(open_input(&opt.file)?)
+    };
+    let (compression, input) = decompress(input, opt.compression).map_err(Error::io(&opt.file))?;
+    println!("Compression: {}", compression);
+    let mut input = BufReader::new(input);
+
+    println!("Output path: {}", outpath.display());
+    let patterns = searcher.patterns().to_vec();
+    let output = create_output(&outpath, opt.no_clobber)?;
+    let input_name = opt.file.display().to_string();
+    let mut output = MatchWriter::new(opt.format, output, &input_name, &patterns)
+        .map_err(Error::io(&outpath))?;
+
+    let now = Instant::now();
+    loop {
+        let buf = fill_buf(&mut input, &opt.file)?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = match selector.next(buf) {
+            Step::Skip(len) => len,
+            Step::Count(len) => {
+                searcher
+                    .search(&buf[..len], |i, offset| {
+                        output.write_match(&patterns[i], offset)
+                    })
+                    .map_err(Error::io(&outpath))?;
+                len
+            }
+            Step::Done => break,
+        };
+        input.consume(len);
+    }
+    output
+        .finish(&patterns, searcher.counts())
+        .and_then(|mut w| w.flush())
+        .map_err(Error::io(&outpath))?;
+
+    for (pattern, count) in patterns.iter().zip(searcher.counts()) {
+        println!("{}: {}", String::from_utf8_lossy(pattern), count);
+    }
+    println!(
+        "Searched: {}, Time: {}",
+        selector.position(),
+        now.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn generate_main(opt: GenerateOptions) -> Result<()> {
+    let prefix = parse_marker(&opt.prefix)
+        .map_err(Error::InvalidArgs)?
+        .unwrap_or_default();
+    let mut generator = Generator::new(&opt.alphabet, opt.seed, opt.noise, opt.runs)?;
+
+    let mut output: Box<dyn Write> = if opt.file == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(create_output(&opt.file, opt.no_clobber)?)
+    };
+
+    let now = Instant::now();
+    generator
+        .write_to(&prefix, opt.size.0, &mut output)
+        .and_then(|()| output.flush())
+        .map_err(Error::io(&opt.file))?;
+    if opt.file == Path::new("-") {
+        return Ok(());
+    }
+    println!("Output path: {}", opt.file.display());
+    println!(
+        "Generated: {}, Time: {}",
+        prefix.len() as u64 + opt.size.0,
+        now.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+fn generate_constant_main(opt: GenerateConstantOptions) -> Result<()> {
+    let mut output: Box<dyn Write> = if opt.file == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(create_output(&opt.file, opt.no_clobber)?)
+    };
+
+    let now = Instant::now();
+    opt.constant
+        .write_to(opt.digits, &opt.alphabet, &mut output)
+        .and_then(|()| output.flush())
+        .map_err(Error::io(&opt.file))?;
+    if opt.file == Path::new("-") {
+        return Ok(());
+    }
+    println!("Output path: {}", opt.file.display());
+    println!(
+        "Digits: {}, Time: {}",
+        opt.digits,
+        now.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// An engine run by the verify subcommand.
+enum VerifiedEngine {
+    Library(DigitCounter),
+    SingleFile(Box<single_file::Variant>),
+}
+
+impl VerifiedEngine {
+    fn on_bytes(&mut self, bytes: &[u8]) {
+        match self {
+            VerifiedEngine::Library(counter) => counter.on_bytes(bytes),
+            VerifiedEngine::SingleFile(imp) => {
+                for &b in bytes {
+                    imp.count_digit(b);
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> Counts {
+        match self {
+            VerifiedEngine::Library(counter) => counter.finish(),
+            VerifiedEngine::SingleFile(mut imp) => {
+                imp.finalize();
+                imp.into_count()
+            }
+        }
+    }
+}
+
+fn verify_main(opt: VerifyOptions) -> Result<()> {
+    opt.validate()?;
+    let mut engines = opt
+        .algorithms()
+        .into_iter()
+        .map(|algorithm| {
+            let counter = CounterBuilder::new(opt.digit)
+                .algorithm(algorithm)
+                .alphabet(opt.alphabet.clone())
+                .build()?;
+            Ok((algorithm.name(), VerifiedEngine::Library(counter)))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if opt.single_file {
+        let imp = Box::new(single_file::Variant::new(opt.digit));
+        engines.push(("single-file", VerifiedEngine::SingleFile(imp)));
+    }
+    let mut times = vec![Duration::default(); engines.len()];
+
+    let input: Box<dyn Read> = if opt.file == Path::new("-") {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(open_input(&opt.file)?)
+    };
+    let (compression, input) = decompress(input, opt.compression).map_err(Error::io(&opt.file))?;
+    println!("Compression: {}", compression);
+    let mut input = BufReader::new(input);
+    let mut selector = Selector::new(&opt.select.selection()?, &opt.alphabet);
+    loop {
+        let buf = fill_buf(&mut input, &opt.file)?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = match selector.next(buf) {
+            Step::Skip(len) => len,
+            Step::Count(len) => {
+                for ((_, engine), time) in engines.iter_mut().zip(&mut times) {
+                    let now = Instant::now();
+                    engine.on_bytes(&buf[..len]);
+                    *time += now.elapsed();
+                }
+                len
+            }
+            Step::Done => break,
+        };
+        input.consume(len);
+    }
+    println!("Verified: {}", selector.position());
+
+    let mut results = Vec::new();
+    for ((name, engine), time) in engines.into_iter().zip(times) {
+        let now = Instant::now();
+        let count = engine.finish();
+        let time = time + now.elapsed();
+        println!(
+            "{}: {} substrings, Time: {}",
+            name,
+            count.len(),
+            time.as_secs_f64()
+        );
+        results.push((name, count));
+    }
+
+    let (reference_name, reference) = &results[0];
+    let mut differing = Vec::new();
+    for (name, count) in &results[1..] {
+        let differences = reference.differences(count);
+        if differences.is_empty() {
+            continue;
+        }
+        println!(
+            "{} differs from {} in {} substrings:",
+            name,
+            reference_name,
+            differences.len()
+        );
+        for (substring, expected, actual) in differences.iter().take(opt.max_differences) {
+            println!(
+                "  {}: {} instead of {}",
+                String::from_utf8_lossy(substring),
+                actual,
+                expected
+            );
+        }
+        differing.push(*name);
+    }
+    if !differing.is_empty() {
+        return Err(Error::CountsDiffer(format!(
+            "the counts of {} differ from {}",
+            differing.join(", "),
+            reference_name
+        )));
+    }
+    println!(
+        "All {} algorithms agree on {} substrings",
+        results.len(),
+        reference.len()
+    );
+    Ok(())
+}
+
+/// A generated input file, removed again when dropped.
+struct TempInput(PathBuf);
+
+impl Drop for TempInput {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn bench_main(opt: BenchOptions) -> Result<()> {
+    opt.validate()?;
+    let selection = opt.select.selection()?;
+    if let Some(output) = &opt.output {
+        check_clobber(output, opt.no_clobber)?;
+    }
+
+    let mut generated = None;
+    let (path, input_name) = match &opt.file {
+        Some(path) => (path.clone(), path.display().to_string()),
+        None => {
+            let path =
+                std::env::temp_dir().join(format!("count-digits-bench-{}.txt", std::process::id()));
+            let temp = TempInput(path.clone());
+            let mut generator =
+                Generator::new(&opt.alphabet, opt.seed, 0.01, RunLengths::Geometric)?;
+            let mut output = create_output(&path, false)?;
+            generator
+                .write_to(b"1.", opt.size.0, &mut output)
+                .and_then(|()| output.flush())
+                .map_err(Error::io(&path))?;
+            generated = Some(temp);
+            let name = format!("generated:{}:seed={}", opt.size.0, opt.seed);
+            (path, name)
+        }
+    };
+    let file_size = open_input(&path)?
+        .metadata()
+        .map_err(Error::io(&path))?
+        .len();
+    let (input_bytes, input_digits) = selected_size(&path, file_size, &selection, &opt.alphabet)?;
+
+    let mut results = Vec::new();
+    for &algorithm in &opt.algorithms {
+        for (mode, capacity) in opt.configurations() {
+            let mut result = BenchResult {
+                algorithm: algorithm.name().to_owned(),
+                mode: mode.name().to_owned(),
+                capacity,
+                times: Vec::new(),
+                peak_rss: None,
+            };
+            for _ in 0..opt.repetitions {
+                let rss_reset = reset_peak_rss();
+                let mut builder = CounterBuilder::new(opt.digit)
+                    .algorithm(algorithm)
+                    .alphabet(opt.alphabet.clone())
+                    .input_size(file_size);
+                if let Some(memory) = available_memory() {
+                    builder = builder.available_memory(memory);
+                }
+                let counter = builder.build()?;
+                let selector = Selector::new(&selection, &opt.alphabet);
+                let time = time_run(counter, &path, mode, capacity, selector)?;
+                let capacity = capacity.map_or(String::new(), |c| format!(" {}", c));
+                eprintln!(
+                    "{} {}{}: {}",
+                    result.algorithm,
+                    result.mode,
+                    capacity,
+                    time.as_secs_f64()
+                );
+                result.times.push(time);
+                if rss_reset {
+                    result.peak_rss = result.peak_rss.max(peak_rss());
+                }
+            }
+            results.push(result);
+        }
+    }
+
+    let report = BenchReport {
+        input: input_name,
+        file_size,
+        digits: opt.digit,
+        input_bytes,
+        input_digits,
+        results: &results,
+    };
+    match &opt.output {
+        Some(path) => {
+            let mut output = create_output(path, opt.no_clobber)?;
+            report
+                .write_to(opt.format, &mut output)
+                .and_then(|()| output.flush())
+                .map_err(Error::io(path))?;
+        }
+        None => {
+            let stdout = io::stdout();
+            report.write_to(opt.format, stdout.lock())?;
+        }
+    }
+    drop(generated);
+    Ok(())
+}
+
+/// The number of selected bytes of `path` and how many of them are digits,
+/// found outside of the timed runs.
+fn selected_size(
+    path: &Path,
+    file_size: u64,
+    selection: &Selection,
+    alphabet: &Alphabet,
+) -> Result<(u64, u64)> {
+    // NB: Empty files can not be mapped.
+    if file_size == 0 {
+        return Ok((0, 0));
+    }
+    let file = open_input(path)?;
+    let memmap = unsafe { memmap::Mmap::map(&file) }.map_err(Error::io(path))?;
+    let bytes = selection.select(&memmap, alphabet);
+    let digits = bytes.iter().filter(|&&b| alphabet.digit(b).is_some());
+    Ok((bytes.len() as u64, digits.count() as u64))
+}
+
+/// Counts `path` once, returning the time of feeding and finalizing the
+/// counter. Building the counts is left out, as is dropping the counter.
+fn time_run(
+    mut counter: DigitCounter,
+    path: &Path,
+    mode: ReadMode,
+    capacity: Option<usize>,
+    mut selector: Selector,
+) -> Result<Duration> {
+    let file = open_input(path)?;
+    let now = Instant::now();
+    match mode {
+        ReadMode::Mmap => {
+            let memmap = unsafe { memmap::Mmap::map(&file) }.map_err(Error::io(path))?;
+            feed(&mut counter, &memmap[..], path, &mut selector)?;
+        }
+        ReadMode::Unmapped => {
+            let capacity = capacity.unwrap_or(STD_CAPACITY);
+            let input = BufReader::with_capacity(capacity, file);
+            feed(&mut counter, input, path, &mut selector)?;
+        }
+    }
+    counter.finalize();
+    Ok(now.elapsed())
+}
+
+fn feed(
+    counter: &mut DigitCounter,
+    mut input: impl BufRead,
+    path: &Path,
+    selector: &mut Selector,
+) -> Result<()> {
+    loop {
+        let buf = fill_buf(&mut input, path)?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let len = match selector.next(buf) {
+            Step::Skip(len) => len,
+            Step::Count(len) => {
+                counter.on_bytes(&buf[..len]);
+                len
+            }
+            Step::Done => return Ok(()),
+        };
+        input.consume(len);
+    }
+}
+
+/// Resets the peak resident set size of this process, returning `false`
+/// where that is not possible, like outside of Linux.
+fn reset_peak_rss() -> bool {
+    // NB: 5 only resets the peak, see proc(5).
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// The peak resident set size of this process in bytes, if known.
+fn peak_rss() -> Option<u64> {
+    proc_kb("/proc/self/status", "VmHWM:")
+}
+
+/// The memory available to new processes in bytes, if known.
+fn available_memory() -> Option<u64> {
+    proc_kb("/proc/meminfo", "MemAvailable:")
+}
+
+/// Reads a size like `VmHWM:   1024 kB` from a file in `/proc`, in bytes.
+fn proc_kb(path: &str, key: &str) -> Option<u64> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let line = text.lines().find(|line| line.starts_with(key))?;
+    let kb = line[key.len()..].trim().trim_end_matches("kB").trim();
+    kb.parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+/// Returns `<dir>/<stem>_<suffix>.<extension>` for the input file `path`.
+fn output_path(path: &Path, suffix: &str, extension: &str) -> Result<PathBuf> {
+    let stem = path.file_stem().ok_or_else(|| {
+        Error::InvalidArgs(format!(
+            "can not derive an output path from {}, use --output",
+            path.display()
+        ))
+    })?;
+    let stem = stem
+        .to_str()
+        .ok_or_else(|| Error::InvalidUtf8Path(path.to_owned()))?;
+    Ok(path.with_file_name(format!("{}_{}.{}", stem, suffix, extension)))
+}
+
+fn write_counts(outpath: &Path, count: &Counts, no_clobber: bool) -> Result<()> {
+    println!("Counts path: {}", outpath.display());
+    let mut output = create_output(outpath, no_clobber)?;
+    count.write_to(&mut output).map_err(Error::io(outpath))?;
+    output.flush().map_err(Error::io(outpath))
+}
+
+fn write_result(outpath: &Path, report: &Report, format: Format, no_clobber: bool) -> Result<()> {
+    let count = report.counts;
 
     if DEBUG_PRINT_COUNTS {
         let mut count = count.iter().collect::<Vec<_>>();
         count.sort();
         for (k, v) in count {
-            println!("[{}]: {:?}", std::str::from_utf8(&k).unwrap(), v);
+            println!("[{}]: {:?}", std::str::from_utf8(k).unwrap(), v);
         }
     }
 
@@ -186,8 +1759,7 @@ fn generic_main<T: Process>(opt: CliOptions) {
         let mut count = count.iter().collect::<Vec<_>>();
         count.sort();
 
-        let file_len = std::fs::metadata(&path).unwrap().len();
-        println!("File size: {}", file_len);
+        println!("File size: {:?}", report.file_size);
 
         let mut counts = Vec::<Counter>::new();
 
@@ -204,21 +1776,16 @@ fn generic_main<T: Process>(opt: CliOptions) {
     }
 
     {
-        println!("Output path: {}", outpath);
-        let mut output = match std::fs::File::create(&outpath) {
-            Ok(file) => file,
-            Err(error) => panic!("{}", error),
-        };
-        for i in 0..digit {
-            let mut filter = count.clone();
-            filter.retain(|k, _| k.len() == i + 1);
-            let tmap = std::collections::BTreeMap::from_iter(filter.iter());
-            write!(output, "{} {:?}\n", tmap.len(), tmap.values()).unwrap();
-        }
+        println!("Output path: {}", outpath.display());
+        let mut output = create_output(outpath, no_clobber)?;
+        report
+            .write_to(format, &mut output)
+            .map_err(Error::io(outpath))?;
+        output.flush().map_err(Error::io(outpath))?;
     }
 
     if DEBUG_COMPUTE_OUTPUT_HASH {
-        let out_bytes = std::fs::read(outpath).unwrap();
+        let out_bytes = std::fs::read(outpath).map_err(Error::io(outpath))?;
 
         let mut hasher = Sha256::new();
 
@@ -228,4 +1795,5 @@ fn generic_main<T: Process>(opt: CliOptions) {
         let result = &format!("{:x}", result)[..8];
         println!("Output hash: {}", result);
     }
+    Ok(())
 }
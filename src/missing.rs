@@ -0,0 +1,158 @@
+//! The substrings of a width that never occurred.
+
+use crate::{Alphabet, Counts};
+use std::convert::TryFrom;
+
+/// The strings of one width that never occurred, in digit order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Missing {
+    pub width: usize,
+    /// Number of missing strings, or `None` if there are too many strings
+    /// of this width to count in a `u128`.
+    pub count: Option<u128>,
+    /// The smallest missing strings, as many as requested but at least
+    /// the very smallest if there is one.
+    pub smallest: Vec<Vec<u8>>,
+}
+
+impl Missing {
+    /// Creates the report from the number of strings that did occur.
+    pub(crate) fn new(width: usize, radix: usize, present: u128, smallest: Vec<Vec<u8>>) -> Self {
+        let possible = u32::try_from(width)
+            .ok()
+            .and_then(|width| (radix as u128).checked_pow(width));
+        Self {
+            width,
+            count: possible.map(|possible| possible - present),
+            smallest,
+        }
+    }
+
+    /// Finds the missing strings of `width` digits by going through the
+    /// gaps between the strings in `counts`. Strings with symbols that are
+    /// not in `alphabet` are skipped.
+    pub fn from_counts(counts: &Counts, alphabet: &Alphabet, width: usize, limit: usize) -> Self {
+        let mut present = counts
+            .iter()
+            .filter(|(s, c)| s.len() == width && *c != 0)
+            .filter_map(|(s, _)| s.iter().map(|&b| alphabet.digit(b)).collect())
+            .collect::<Vec<Vec<u8>>>();
+        present.sort_unstable();
+
+        let radix = alphabet.radix();
+        let mut smallest = Vec::new();
+        let mut next = Some(vec![0; width]);
+        let mut present_iter = present.iter().peekable();
+        while let Some(candidate) = next.take() {
+            if smallest.len() >= limit.max(1) {
+                break;
+            }
+            if present_iter.peek() == Some(&&candidate) {
+                present_iter.next();
+            } else {
+                smallest.push(candidate.iter().map(|&d| alphabet.symbol(d)).collect());
+            }
+            next = increment(candidate, radix);
+        }
+        Self::new(width, radix, present.len() as u128, smallest)
+    }
+
+    pub fn smallest(&self) -> Option<&[u8]> {
+        self.smallest.first().map(|s| &s[..])
+    }
+}
+
+/// Returns the next string of digits in order, or `None` after the last.
+fn increment(mut digits: Vec<u8>, radix: usize) -> Option<Vec<u8>> {
+    for d in digits.iter_mut().rev() {
+        if (*d as usize) + 1 < radix {
+            *d += 1;
+            return Some(digits);
+        }
+        *d = 0;
+    }
+    None
+}
+
+/// Calls `f` with every number in `0..=last` that is not in the sorted
+/// `present`, in increasing order, until it returns `false`.
+pub(crate) fn for_each_gap<N>(present: &[N], last: N, mut f: impl FnMut(N) -> bool)
+where
+    N: Copy + Ord + Default + From<u8> + std::ops::Add<Output = N>,
+{
+    let mut next = N::default();
+    for &p in present {
+        while next < p {
+            if !f(next) {
+                return;
+            }
+            next = next + N::from(1);
+        }
+        if p == last {
+            return;
+        }
+        next = p + N::from(1);
+    }
+    loop {
+        if !f(next) || next == last {
+            return;
+        }
+        next = next + N::from(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_counts() {
+        let mut counts = Counts::new(2);
+        for s in &["0", "1", "2", "3", "00", "01", "10", "33"] {
+            counts.add(s.as_bytes(), 1);
+        }
+        let alphabet = Alphabet::new(b"0123").unwrap();
+        let missing = Missing::from_counts(&counts, &alphabet, 2, 3);
+        assert_eq!(missing.count, Some(12));
+        assert_eq!(
+            missing.smallest,
+            vec![b"02".to_vec(), b"03".to_vec(), b"11".to_vec()]
+        );
+
+        let full = Missing::from_counts(&counts, &alphabet, 1, 0);
+        assert_eq!((full.count, full.smallest()), (Some(0), None));
+        assert_eq!(
+            Missing::from_counts(&counts, &alphabet, 2, 0).smallest(),
+            Some(&b"02"[..])
+        );
+    }
+
+    #[test]
+    fn test_foreign_symbols() {
+        let mut counts = Counts::new(2);
+        for s in &["1", "2", "12", "A", "C", "AC"] {
+            counts.add(s.as_bytes(), 1);
+        }
+        let alphabet = "ACGT".parse::<Alphabet>().unwrap();
+        let missing = Missing::from_counts(&counts, &alphabet, 2, 1);
+        assert_eq!(missing.count, Some(15));
+        assert_eq!(missing.smallest(), Some(&b"AA"[..]));
+    }
+
+    #[test]
+    fn test_gaps() {
+        let gaps = |present: &[u64], last: u64, limit: usize| {
+            let mut out = Vec::new();
+            for_each_gap(present, last, |n| {
+                out.push(n);
+                out.len() < limit
+            });
+            out
+        };
+        assert_eq!(gaps(&[0, 2, 3, 6], 7, 10), vec![1, 4, 5, 7]);
+        assert_eq!(gaps(&[0, 2, 3, 6], 7, 2), vec![1, 4]);
+        assert_eq!(gaps(&[], 2, 10), vec![0, 1, 2]);
+        assert_eq!(gaps(&[0, 1, 2], 2, 10), Vec::<u64>::new());
+        assert_eq!(gaps(&[u64::MAX], u64::MAX, 1), vec![0]);
+    }
+}
@@ -0,0 +1,64 @@
+//! Where each digit substring occurred first.
+
+use crate::FastHashMap;
+use std::collections::BTreeMap;
+
+/// The byte offset of the first occurrence of each digit substring.
+///
+/// Offsets are of the first digit of the substring and relative to the
+/// start of the counted input, which is right after the marker. Keys are
+/// like those of [`crate::Counts`], substrings that never occurred are
+/// absent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FirstOffsets {
+    map: FastHashMap<Vec<u8>, u64>,
+    digits: usize,
+}
+
+impl FirstOffsets {
+    pub fn new(digits: usize) -> Self {
+        Self::from_map(digits, FastHashMap::default())
+    }
+
+    pub fn from_map(digits: usize, map: FastHashMap<Vec<u8>, u64>) -> Self {
+        Self { map, digits }
+    }
+
+    /// The maximum substring width that was recorded.
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    /// Returns the first offset of `substring`, or `None` if it never
+    /// occurred.
+    pub fn get(&self, substring: &[u8]) -> Option<u64> {
+        self.map.get(substring).copied()
+    }
+
+    /// Records that `substring` occurred at `offset`, keeping the earlier
+    /// offset if there already is one.
+    pub fn insert(&mut self, substring: &[u8], offset: u64) {
+        if let Some(first) = self.map.get_mut(substring) {
+            *first = offset.min(*first);
+        } else {
+            self.map.insert(substring.to_vec(), offset);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], u64)> {
+        self.map.iter().map(|(k, v)| (&k[..], *v))
+    }
+
+    /// All substrings of exactly `width` digits, sorted.
+    pub fn width(&self, width: usize) -> BTreeMap<&[u8], u64> {
+        self.iter().filter(|(k, _)| k.len() == width).collect()
+    }
+}
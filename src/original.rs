@@ -1,38 +1,41 @@
-use crate::{Counter, FastHashMap, Process};
+use crate::{Alphabet, Counter, Counts, FastHashMap, Process};
 
 // by @ehf
 
-fn is_not_numeric<T: NumericType>(vec: &Vec<u8>) -> bool {
+fn is_not_numeric<T: NumericType>(alphabet: &Alphabet, vec: &[u8]) -> bool {
     for &c in vec {
-        if !T::is_numeric(c) {
+        if !T::is_numeric(alphabet, c) {
             return true;
         }
     }
-    return false;
+    false
 }
 
 pub trait NumericType: Default {
-    fn is_numeric(v: u8) -> bool;
+    fn is_numeric(alphabet: &Alphabet, v: u8) -> bool;
 }
 
+/// Accepts what `char::is_numeric` accepts, regardless of the alphabet.
 #[derive(Default)]
 pub struct StdNumeric;
 impl NumericType for StdNumeric {
-    fn is_numeric(v: u8) -> bool {
+    fn is_numeric(_alphabet: &Alphabet, v: u8) -> bool {
         char::from(v).is_numeric()
     }
 }
+/// Accepts the digits of the alphabet.
 #[derive(Default)]
-pub struct HexDigit;
-impl NumericType for HexDigit {
-    fn is_numeric(v: u8) -> bool {
-        (v >= b'0' && v <= b'9') || (v >= b'a' && v <= b'f') || (v >= b'A' && v <= b'F')
+pub struct AlphabetDigit;
+impl NumericType for AlphabetDigit {
+    fn is_numeric(alphabet: &Alphabet, v: u8) -> bool {
+        alphabet.digit(v).is_some()
     }
 }
 
 pub struct Original<T> {
     count: FastHashMap<Vec<u8>, Counter>,
     buffer: Vec<Vec<u8>>,
+    alphabet: Alphabet,
     _numeric_type: T,
 }
 
@@ -41,11 +44,15 @@ fn _debug_print(b: &[u8], i: usize) {
 }
 
 impl<T: NumericType> Process for Original<T> {
-    fn new(digit: usize) -> Self {
+    fn new(digit: usize, alphabet: &Alphabet) -> Self {
         let count: FastHashMap<Vec<u8>, Counter> = FastHashMap::default();
+        // NB: The padding must not be a digit itself.
+        let pad = (0..=255u8)
+            .find(|&b| !T::is_numeric(alphabet, b))
+            .unwrap_or(b'_');
         let mut buffer: Vec<Vec<u8>> = Vec::with_capacity(digit);
         for i in 0..digit {
-            let b = b"_".repeat(i + 1);
+            let b = vec![pad; i + 1];
             //_debug_print(&b, buffer.len());
             buffer.push(b);
         }
@@ -55,6 +62,7 @@ impl<T: NumericType> Process for Original<T> {
         Self {
             count,
             buffer,
+            alphabet: alphabet.clone(),
             _numeric_type,
         }
     }
@@ -62,7 +70,7 @@ impl<T: NumericType> Process for Original<T> {
         //println!("Byte '{}'", byte as char);
         for i in 0..(self.buffer.len()) {
             self.buffer[i].remove(0);
-            let byte = normalize_hex_byte(byte);
+            let byte = self.alphabet.normalize(byte);
             self.buffer[i].push(byte);
             //_debug_print(&self.buffer[i], i);
             *self.count.entry(self.buffer[i].clone()).or_insert(0) += 1;
@@ -70,21 +78,12 @@ impl<T: NumericType> Process for Original<T> {
     }
     fn finalize(&mut self) {
         for key in self.count.clone().keys() {
-            if is_not_numeric::<T>(key) {
+            if is_not_numeric::<T>(&self.alphabet, key) {
                 self.count.remove(key);
             }
         }
     }
-    fn into_count(self) -> FastHashMap<Vec<u8>, Counter> {
-        self.count
-    }
-}
-
-fn normalize_hex_byte(b: u8) -> u8 {
-    if b >= b'A' && b <= b'F' {
-        let off = b - b'A';
-        b'a' + off
-    } else {
-        b
+    fn into_count(self) -> Counts {
+        Counts::from_map(self.buffer.len(), self.count)
     }
 }
@@ -0,0 +1,158 @@
+//! Counting of a memory mapped file on several threads.
+//!
+//! The input is split into one chunk per thread and each chunk is counted
+//! by its own `Variant`. A chunk knows nothing about its neighbours, so
+//! digit strings crossing a chunk boundary are missed by both sides. After
+//! merging, those are counted directly from the up to `digits - 1` bytes
+//! on either side of each boundary.
+
+use crate::variant::{CountStrategy, CounterStorage, Number, Variant};
+use crate::{Alphabet, Process};
+
+/// Returns the number of threads to use when none was requested.
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+/// Counts all digit strings in `bytes` on `threads` threads.
+///
+/// The result is already finalized and gives the same counts as feeding
+/// every byte to a single `Variant`. First offsets are recorded if
+/// `first_offset` is the offset of `bytes[0]`.
+pub fn count_chunked<T, U, N>(
+    bytes: &[u8],
+    digits: usize,
+    alphabet: &Alphabet,
+    threads: usize,
+    first_offset: Option<u64>,
+) -> Variant<T, U, N>
+where
+    T: CountStrategy + Send,
+    N: Number,
+    U: for<'a> CounterStorage<'a, N> + Send,
+{
+    let boundaries = chunk_boundaries(bytes.len(), threads);
+
+    let mut parts = std::thread::scope(|scope| {
+        let handles = boundaries
+            .windows(2)
+            .map(|w| {
+                let chunk = &bytes[w[0]..w[1]];
+                scope.spawn(move || {
+                    let mut imp = Variant::<T, U, N>::new(digits, alphabet);
+                    if let Some(offset) = first_offset {
+                        imp.record_first_offsets(offset + w[0] as u64);
+                    }
+                    imp.count_bytes(chunk);
+                    imp.finalize();
+                    imp
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut imp = parts.remove(0);
+    for part in parts {
+        imp.merge(part);
+    }
+    // NB: Strings are only stitched at the first boundary they cross, as
+    // chunks can be shorter than a string.
+    for w in boundaries[..boundaries.len() - 1].windows(2) {
+        let offset = first_offset.unwrap_or(0) + w[0] as u64;
+        imp.count_straddling(&bytes[w[0]..], w[1] - w[0], offset);
+    }
+    imp
+}
+
+/// Splits `len` bytes into at most `threads` non-empty chunks.
+///
+/// Returns the chunk start offsets followed by `len`.
+fn chunk_boundaries(len: usize, threads: usize) -> Vec<usize> {
+    let chunks = threads.max(1).min(len.max(1));
+    (0..chunks)
+        .map(|i| i * len / chunks)
+        .chain(std::iter::once(len))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::{EarlyCount, HashMapCounter, LateCount, VecCounter};
+
+    fn sequential<T, U>(bytes: &[u8], digits: usize) -> crate::Counts
+    where
+        T: CountStrategy,
+        U: for<'a> CounterStorage<'a>,
+    {
+        let mut imp = Variant::<T, U>::new(digits, &Alphabet::hex());
+        for &byte in bytes {
+            imp.on_byte(byte);
+        }
+        imp.finalize();
+        imp.into_count()
+    }
+
+    #[test]
+    fn test_chunked_matches_sequential() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        for digits in 1..5 {
+            for &threads in &[1, 2, 3, 7, 16, input.len() + 1] {
+                let expected = sequential::<LateCount, VecCounter>(input, digits);
+                let counts = count_chunked::<LateCount, VecCounter, u64>(
+                    input,
+                    digits,
+                    &Alphabet::hex(),
+                    threads,
+                    None,
+                )
+                .into_count();
+                assert_eq!(counts, expected, "digits={} threads={}", digits, threads);
+                let counts = count_chunked::<EarlyCount, HashMapCounter<u128>, u128>(
+                    input,
+                    digits,
+                    &Alphabet::hex(),
+                    threads,
+                    None,
+                )
+                .into_count();
+                assert_eq!(counts, expected, "digits={} threads={}", digits, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunked_first_offsets() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        for digits in 1..5 {
+            let mut imp = Variant::<LateCount, HashMapCounter>::new(digits, &Alphabet::hex());
+            imp.record_first_offsets(7);
+            imp.count_bytes(input);
+            imp.finalize();
+            let expected = imp.first_offsets();
+            for &threads in &[2, 3, 16] {
+                let mut imp = count_chunked::<EarlyCount, VecCounter, u64>(
+                    input,
+                    digits,
+                    &Alphabet::hex(),
+                    threads,
+                    Some(7),
+                );
+                assert_eq!(imp.first_offsets(), expected, "threads={}", threads);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunk_boundaries() {
+        assert_eq!(chunk_boundaries(10, 3), vec![0, 3, 6, 10]);
+        assert_eq!(chunk_boundaries(2, 4), vec![0, 1, 2]);
+        assert_eq!(chunk_boundaries(0, 4), vec![0, 0]);
+    }
+}
@@ -0,0 +1,170 @@
+//! The most and least frequent substrings of a width.
+
+use crate::{Counter, Counts};
+use std::collections::BinaryHeap;
+use std::fmt;
+
+/// Which end of the frequencies a [`Ranking`] lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Most frequent first.
+    Top,
+    /// Least frequent first, among the substrings that occurred at all.
+    Bottom,
+}
+
+impl Order {
+    pub fn name(self) -> &'static str {
+        match self {
+            Order::Top => "top",
+            Order::Bottom => "bottom",
+        }
+    }
+
+    /// Maps counts so that the first ranked ones are the smallest.
+    fn badness(self, count: Counter) -> Counter {
+        match self {
+            Order::Top => Counter::MAX - count,
+            Order::Bottom => count,
+        }
+    }
+}
+
+impl fmt::Display for Order {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The `k` most or least frequent substrings of one width.
+///
+/// Substrings tied with the last of the `k` are included as well, so there
+/// can be more than `k` entries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ranking {
+    pub width: usize,
+    pub order: Order,
+    pub k: usize,
+    /// Substrings and their counts, best first and then by substring.
+    pub entries: Vec<(Vec<u8>, Counter)>,
+}
+
+impl Ranking {
+    pub fn new(width: usize, order: Order, k: usize, mut entries: Vec<(Vec<u8>, Counter)>) -> Self {
+        entries.sort_by(|(a, x), (b, y)| (order.badness(*x), a).cmp(&(order.badness(*y), b)));
+        Self {
+            width,
+            order,
+            k,
+            entries,
+        }
+    }
+
+    /// Ranks `counts` by iterating all substrings of `width` digits.
+    pub fn from_counts(counts: &Counts, width: usize, order: Order, k: usize) -> Self {
+        let entries = select(k, order, |f| {
+            for (substring, count) in counts.iter() {
+                if substring.len() == width {
+                    f(substring, count);
+                }
+            }
+        });
+        let entries = entries.into_iter().map(|(s, c)| (s.to_vec(), c));
+        Self::new(width, order, k, entries.collect())
+    }
+
+    /// The entries with their rank, starting at 1. Tied entries share the
+    /// rank, and the next one skips as many ranks as there were ties.
+    pub fn ranked(&self) -> impl Iterator<Item = (usize, &[u8], Counter)> {
+        let mut rank = 0;
+        let mut prev = None;
+        self.entries.iter().enumerate().map(move |(i, (s, c))| {
+            if prev != Some(*c) {
+                rank = i + 1;
+                prev = Some(*c);
+            }
+            (rank, &s[..], *c)
+        })
+    }
+}
+
+/// Returns the `k` best entries produced by `for_each`, and all entries
+/// tied with the last of them, in no particular order. Zero counts are
+/// ignored.
+///
+/// Only the counts of the best `k` are kept in a heap while finding the
+/// cut-off, so this works on count tables of any size. `for_each` is
+/// called twice.
+pub(crate) fn select<K>(
+    k: usize,
+    order: Order,
+    mut for_each: impl FnMut(&mut dyn FnMut(K, Counter)),
+) -> Vec<(K, Counter)> {
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for_each(&mut |_, count| {
+        if count == 0 {
+            return;
+        }
+        heap.push(order.badness(count));
+        if heap.len() > k {
+            heap.pop();
+        }
+    });
+    let cutoff = match heap.peek() {
+        Some(&cutoff) => cutoff,
+        None => return Vec::new(),
+    };
+
+    let mut entries = Vec::with_capacity(k);
+    for_each(&mut |key, count| {
+        if count != 0 && order.badness(count) <= cutoff {
+            entries.push((key, count));
+        }
+    });
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ties() {
+        let mut counts = Counts::new(2);
+        for (k, v) in &[
+            ("1", 9),
+            ("12", 5),
+            ("34", 5),
+            ("56", 4),
+            ("78", 7),
+            ("9a", 4),
+        ] {
+            counts.add(k.as_bytes(), *v);
+        }
+        let top = Ranking::from_counts(&counts, 2, Order::Top, 2);
+        assert_eq!(
+            top.ranked().collect::<Vec<_>>(),
+            vec![(1, &b"78"[..], 7), (2, b"12", 5), (2, b"34", 5)]
+        );
+        let bottom = Ranking::from_counts(&counts, 2, Order::Bottom, 1);
+        assert_eq!(
+            bottom.ranked().collect::<Vec<_>>(),
+            vec![(1, &b"56"[..], 4), (1, b"9a", 4)]
+        );
+        assert_eq!(
+            Ranking::from_counts(&counts, 2, Order::Top, 10)
+                .entries
+                .len(),
+            5
+        );
+        assert!(Ranking::from_counts(&counts, 2, Order::Top, 0)
+            .entries
+            .is_empty());
+        assert!(Ranking::from_counts(&counts, 3, Order::Top, 3)
+            .entries
+            .is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+//! The obvious, slow way of counting, which the engines are tested against.
+
+use crate::{Alphabet, Counts};
+
+/// Counts every substring of `digits` or fewer digits in `bytes` by looking
+/// at each window on its own. Keys are normalized like those of the engines.
+pub fn count(bytes: &[u8], digits: usize, alphabet: &Alphabet) -> Counts {
+    let normalized = bytes
+        .iter()
+        .map(|&b| alphabet.digit(b).map(|d| alphabet.symbol(d)))
+        .collect::<Vec<_>>();
+    let mut counts = Counts::new(digits);
+    for start in 0..normalized.len() {
+        let mut substring = Vec::new();
+        for &symbol in normalized[start..].iter().take(digits) {
+            match symbol {
+                Some(symbol) => substring.push(symbol),
+                None => break,
+            }
+            counts.add(&substring, 1);
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        let counts = count(b"1AB_ab\n1", 2, &Alphabet::hex());
+        let mut expected = Counts::new(2);
+        for &(k, v) in &[("1", 2), ("a", 2), ("b", 2), ("1a", 1), ("ab", 2)] {
+            expected.add(k.as_bytes(), v);
+        }
+        assert_eq!(counts, expected);
+    }
+}
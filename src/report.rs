@@ -0,0 +1,1160 @@
+use crate::{Counter, Counts, FirstOffsets, Missing, Ranking, Stats};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The file format of a count result.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// One line per width with the number of distinct substrings and their
+    /// counts, without the substrings themselves. This is the historic
+    /// `_result.txt` format.
+    Text,
+    /// One object with the metadata and a `counts` array of records.
+    Json,
+    /// The metadata object on the first line, then one record per line.
+    Ndjson,
+    /// `# key: value` metadata lines, then a header row and one record per row.
+    Csv,
+    /// Like `Csv`, with tabs as separators.
+    Tsv,
+}
+
+impl Format {
+    pub const ALL: &'static [Format] = &[
+        Format::Text,
+        Format::Json,
+        Format::Ndjson,
+        Format::Csv,
+        Format::Tsv,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+        }
+    }
+
+    /// The usual file extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            other => other.name(),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|f| f.name() == s)
+            .ok_or_else(|| {
+                let names = Format::ALL.iter().map(|f| f.name()).collect::<Vec<_>>();
+                format!(
+                    "unknown format {:?}, expected one of {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A count result together with where it came from.
+///
+/// Every format except [`Format::Text`] writes `(width, substring, count)`
+/// records, sorted by width and then by substring, after a header with
+/// this metadata. Rankings, missing substrings, statistics and first
+/// offsets are written separately, see [`Report::write_rankings_to`],
+/// [`Report::write_missing_to`], [`Report::write_stats_to`] and
+/// [`Report::write_first_offsets_to`].
+pub struct Report<'a> {
+    /// The input path, as given.
+    pub input: String,
+    /// Size of the input in bytes, if known.
+    pub file_size: Option<u64>,
+    pub algorithm: String,
+    /// Time spent counting, without reading the input up front.
+    pub elapsed: Duration,
+    pub counts: &'a Counts,
+    pub rankings: &'a [Ranking],
+    pub missing: &'a [Missing],
+    pub stats: &'a [Stats],
+    pub first_offsets: Option<&'a FirstOffsets>,
+}
+
+impl Report<'_> {
+    pub fn write_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        match format {
+            Format::Text => self.write_text(w),
+            Format::Json => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                write!(w, ",\"counts\":[")?;
+                let mut first = true;
+                for (width, substring, count) in self.records() {
+                    if !first {
+                        write!(w, ",")?;
+                    }
+                    first = false;
+                    write_json_record(&mut w, width, substring, count)?;
+                }
+                writeln!(w, "]}}")
+            }
+            Format::Ndjson => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                writeln!(w, "}}")?;
+                for (width, substring, count) in self.records() {
+                    write_json_record(&mut w, width, substring, count)?;
+                    writeln!(w)?;
+                }
+                Ok(())
+            }
+            Format::Csv => self.write_separated(w, b','),
+            Format::Tsv => self.write_separated(w, b'\t'),
+        }
+    }
+
+    /// Writes the rankings with the same metadata, as
+    /// `(width, order, rank, substring, count)` records.
+    pub fn write_rankings_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        match format {
+            Format::Text => {
+                for ranking in self.rankings {
+                    writeln!(
+                        w,
+                        "{} {} of width {}:",
+                        ranking.order, ranking.k, ranking.width
+                    )?;
+                    for (rank, substring, count) in ranking.ranked() {
+                        write!(w, "{} ", rank)?;
+                        w.write_all(substring)?;
+                        writeln!(w, " {}", count)?;
+                    }
+                }
+                Ok(())
+            }
+            Format::Json => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                write!(w, ",\"rankings\":[")?;
+                for (i, ranking) in self.rankings.iter().enumerate() {
+                    if i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(
+                        w,
+                        "{{\"width\":{},\"order\":\"{}\",\"k\":{},\"entries\":[",
+                        ranking.width, ranking.order, ranking.k
+                    )?;
+                    for (j, (rank, substring, count)) in ranking.ranked().enumerate() {
+                        if j != 0 {
+                            write!(w, ",")?;
+                        }
+                        write!(w, "{{\"rank\":{},\"substring\":", rank)?;
+                        write_json_string(&mut w, substring)?;
+                        write!(w, ",\"count\":{}}}", count)?;
+                    }
+                    write!(w, "]}}")?;
+                }
+                writeln!(w, "]}}")
+            }
+            Format::Ndjson => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                writeln!(w, "}}")?;
+                for ranking in self.rankings {
+                    for (rank, substring, count) in ranking.ranked() {
+                        write!(
+                            w,
+                            "{{\"width\":{},\"order\":\"{}\",\"rank\":{},\"substring\":",
+                            ranking.width, ranking.order, rank
+                        )?;
+                        write_json_string(&mut w, substring)?;
+                        writeln!(w, ",\"count\":{}}}", count)?;
+                    }
+                }
+                Ok(())
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if format == Format::Csv { b',' } else { b'\t' };
+                self.write_separated_metadata(&mut w)?;
+                let c = sep as char;
+                writeln!(w, "width{}order{}rank{}substring{}count", c, c, c, c)?;
+                for ranking in self.rankings {
+                    for (rank, substring, count) in ranking.ranked() {
+                        write!(
+                            w,
+                            "{}{}{}{}{}{}",
+                            ranking.width, c, ranking.order, c, rank, c
+                        )?;
+                        write_separated_field(&mut w, substring, sep)?;
+                        writeln!(w, "{}{}", c, count)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the missing substrings with the same metadata. The count of a
+    /// width is empty or `null` if it does not fit into a `u128`.
+    pub fn write_missing_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        let count = |missing: &Missing| missing.count.map(|c| c.to_string());
+        match format {
+            Format::Text => {
+                for missing in self.missing {
+                    let count = count(missing).unwrap_or_else(|| "over 2^128".to_owned());
+                    writeln!(w, "width {}: {} missing", missing.width, count)?;
+                    for s in &missing.smallest {
+                        w.write_all(s)?;
+                        writeln!(w)?;
+                    }
+                }
+                Ok(())
+            }
+            Format::Json | Format::Ndjson => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                if format == Format::Json {
+                    write!(w, ",\"missing\":[")?;
+                } else {
+                    writeln!(w, "}}")?;
+                }
+                for (i, missing) in self.missing.iter().enumerate() {
+                    if format == Format::Json && i != 0 {
+                        write!(w, ",")?;
+                    }
+                    let count = count(missing).unwrap_or_else(|| "null".to_owned());
+                    write!(
+                        w,
+                        "{{\"width\":{},\"count\":{},\"smallest\":",
+                        missing.width, count
+                    )?;
+                    match missing.smallest() {
+                        Some(s) => write_json_string(&mut w, s)?,
+                        None => write!(w, "null")?,
+                    }
+                    write!(w, ",\"strings\":[")?;
+                    for (j, s) in missing.smallest.iter().enumerate() {
+                        if j != 0 {
+                            write!(w, ",")?;
+                        }
+                        write_json_string(&mut w, s)?;
+                    }
+                    write!(w, "]}}")?;
+                    if format == Format::Ndjson {
+                        writeln!(w)?;
+                    }
+                }
+                if format == Format::Json {
+                    writeln!(w, "]}}")?;
+                }
+                Ok(())
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if format == Format::Csv { b',' } else { b'\t' };
+                self.write_separated_metadata(&mut w)?;
+                let c = sep as char;
+                writeln!(w, "width{}missing{}substring", c, c)?;
+                for missing in self.missing {
+                    let count = count(missing).unwrap_or_default();
+                    if missing.smallest.is_empty() {
+                        writeln!(w, "{}{}{}{}", missing.width, c, count, c)?;
+                    }
+                    for s in &missing.smallest {
+                        write!(w, "{}{}{}{}", missing.width, c, count, c)?;
+                        write_separated_field(&mut w, s, sep)?;
+                        writeln!(w)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the statistics of each width with the same metadata.
+    pub fn write_stats_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        match format {
+            Format::Text => {
+                for stats in self.stats {
+                    writeln!(
+                        w,
+                        "width {}: chi-squared {} with {} degrees of freedom, p-value {}",
+                        stats.width,
+                        float(stats.chi_squared),
+                        float(stats.degrees_of_freedom),
+                        float(stats.p_value)
+                    )?;
+                    writeln!(
+                        w,
+                        "  entropy {} of {} bits, max count {} (+{}), min count {} ({})",
+                        float(stats.entropy),
+                        float(stats.max_entropy),
+                        stats.max_count,
+                        float(stats.max_deviation),
+                        stats.min_count,
+                        float(stats.min_deviation)
+                    )?;
+                }
+                Ok(())
+            }
+            Format::Json | Format::Ndjson => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                if format == Format::Json {
+                    write!(w, ",\"stats\":[")?;
+                } else {
+                    writeln!(w, "}}")?;
+                }
+                for (i, stats) in self.stats.iter().enumerate() {
+                    if format == Format::Json && i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(w, "{{")?;
+                    for (j, (name, value)) in stats_fields(stats).iter().enumerate() {
+                        if j != 0 {
+                            write!(w, ",")?;
+                        }
+                        write!(w, "\"{}\":{}", name, value)?;
+                    }
+                    write!(w, "}}")?;
+                    if format == Format::Ndjson {
+                        writeln!(w)?;
+                    }
+                }
+                if format == Format::Json {
+                    writeln!(w, "]}}")?;
+                }
+                Ok(())
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if format == Format::Csv { "," } else { "\t" };
+                self.write_separated_metadata(&mut w)?;
+                let mut header = true;
+                for stats in self.stats {
+                    let fields = stats_fields(stats);
+                    if header {
+                        let names = fields.iter().map(|(name, _)| *name);
+                        writeln!(w, "{}", names.collect::<Vec<_>>().join(sep))?;
+                        header = false;
+                    }
+                    let values = fields.iter().map(|(_, value)| &value[..]);
+                    writeln!(w, "{}", values.collect::<Vec<_>>().join(sep))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes every substring with its count and first offset, as
+    /// `(width, substring, count, first_offset)` records after the same
+    /// metadata. The text format has one `substring count first_offset`
+    /// line per substring.
+    pub fn write_first_offsets_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        let records = self.records().map(|(width, substring, count)| {
+            let first = self.first_offsets.and_then(|first| first.get(substring));
+            (width, substring, count, first)
+        });
+        let first_str = |first: Option<u64>, none: &str| match first {
+            Some(first) => first.to_string(),
+            None => none.to_owned(),
+        };
+        match format {
+            Format::Text => {
+                for (_, substring, count, first) in records {
+                    w.write_all(substring)?;
+                    writeln!(w, " {} {}", count, first_str(first, "-"))?;
+                }
+                Ok(())
+            }
+            Format::Json | Format::Ndjson => {
+                write!(w, "{{")?;
+                self.write_json_metadata(&mut w)?;
+                if format == Format::Json {
+                    write!(w, ",\"first_offsets\":[")?;
+                } else {
+                    writeln!(w, "}}")?;
+                }
+                for (i, (width, substring, count, first)) in records.enumerate() {
+                    if format == Format::Json && i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(w, "{{\"width\":{},\"substring\":", width)?;
+                    write_json_string(&mut w, substring)?;
+                    write!(
+                        w,
+                        ",\"count\":{},\"first_offset\":{}}}",
+                        count,
+                        first_str(first, "null")
+                    )?;
+                    if format == Format::Ndjson {
+                        writeln!(w)?;
+                    }
+                }
+                if format == Format::Json {
+                    writeln!(w, "]}}")?;
+                }
+                Ok(())
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if format == Format::Csv { b',' } else { b'\t' };
+                self.write_separated_metadata(&mut w)?;
+                let c = sep as char;
+                writeln!(w, "width{}substring{}count{}first_offset", c, c, c)?;
+                for (width, substring, count, first) in records {
+                    write!(w, "{}{}", width, c)?;
+                    write_separated_field(&mut w, substring, sep)?;
+                    writeln!(w, "{}{}{}{}", c, count, c, first_str(first, ""))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn records(&self) -> impl Iterator<Item = (usize, &[u8], Counter)> {
+        (1..self.counts.digits() + 1).flat_map(move |width| {
+            self.counts
+                .width(width)
+                .into_iter()
+                .map(move |(k, v)| (width, k, v))
+        })
+    }
+
+    fn write_text(&self, mut w: impl Write) -> io::Result<()> {
+        for width in 1..self.counts.digits() + 1 {
+            let tmap = self.counts.width(width);
+            writeln!(w, "{} {:?}", tmap.len(), tmap.values())?;
+        }
+        Ok(())
+    }
+
+    fn write_json_metadata(&self, mut w: impl Write) -> io::Result<()> {
+        write!(w, "\"input\":")?;
+        write_json_string(&mut w, self.input.as_bytes())?;
+        match self.file_size {
+            Some(size) => write!(w, ",\"file_size\":{}", size)?,
+            None => write!(w, ",\"file_size\":null")?,
+        }
+        write!(w, ",\"digits\":{},\"algorithm\":", self.counts.digits())?;
+        write_json_string(&mut w, self.algorithm.as_bytes())?;
+        write!(w, ",\"elapsed_secs\":{}", self.elapsed.as_secs_f64())
+    }
+
+    fn write_separated(&self, mut w: impl Write, sep: u8) -> io::Result<()> {
+        self.write_separated_metadata(&mut w)?;
+        let c = sep as char;
+        writeln!(w, "width{}substring{}count", c, c)?;
+        for (width, substring, count) in self.records() {
+            write!(w, "{}{}", width, c)?;
+            write_separated_field(&mut w, substring, sep)?;
+            writeln!(w, "{}{}", c, count)?;
+        }
+        Ok(())
+    }
+
+    fn write_separated_metadata(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "# input: {}", self.input)?;
+        match self.file_size {
+            Some(size) => writeln!(w, "# file_size: {}", size)?,
+            None => writeln!(w, "# file_size:")?,
+        }
+        writeln!(w, "# digits: {}", self.counts.digits())?;
+        writeln!(w, "# algorithm: {}", self.algorithm)?;
+        writeln!(w, "# elapsed_secs: {}", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Writes the matches of a search as they are found, followed by the
+/// number of matches of each pattern.
+///
+/// The separated formats write `(pattern, offset)` records and end with
+/// `# count <pattern>: <count>` lines, the text format has one
+/// `<offset> <pattern>` line per match and `total <pattern> <count>` lines.
+pub struct MatchWriter<W> {
+    w: W,
+    format: Format,
+    matches: u64,
+}
+
+impl<W: Write> MatchWriter<W> {
+    /// Writes the header, with the searched `input` and `patterns`.
+    pub fn new(format: Format, mut w: W, input: &str, patterns: &[Vec<u8>]) -> io::Result<Self> {
+        match format {
+            Format::Text => {}
+            Format::Json | Format::Ndjson => {
+                write!(w, "{{\"input\":")?;
+                write_json_string(&mut w, input.as_bytes())?;
+                write!(w, ",\"patterns\":[")?;
+                for (i, pattern) in patterns.iter().enumerate() {
+                    if i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write_json_string(&mut w, pattern)?;
+                }
+                if format == Format::Json {
+                    write!(w, "],\"matches\":[")?;
+                } else {
+                    writeln!(w, "]}}")?;
+                }
+            }
+            Format::Csv | Format::Tsv => {
+                writeln!(w, "# input: {}", input)?;
+                let patterns = patterns.iter().map(|p| String::from_utf8_lossy(p));
+                writeln!(w, "# patterns: {}", patterns.collect::<Vec<_>>().join(" "))?;
+                let c = if format == Format::Csv { ',' } else { '\t' };
+                writeln!(w, "pattern{}offset", c)?;
+            }
+        }
+        Ok(Self {
+            w,
+            format,
+            matches: 0,
+        })
+    }
+
+    pub fn write_match(&mut self, pattern: &[u8], offset: u64) -> io::Result<()> {
+        let w = &mut self.w;
+        match self.format {
+            Format::Text => {
+                write!(w, "{} ", offset)?;
+                w.write_all(pattern)?;
+                writeln!(w)?;
+            }
+            Format::Json | Format::Ndjson => {
+                if self.format == Format::Json && self.matches != 0 {
+                    write!(w, ",")?;
+                }
+                write!(w, "{{\"pattern\":")?;
+                write_json_string(&mut *w, pattern)?;
+                write!(w, ",\"offset\":{}}}", offset)?;
+                if self.format == Format::Ndjson {
+                    writeln!(w)?;
+                }
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if self.format == Format::Csv {
+                    b','
+                } else {
+                    b'\t'
+                };
+                write_separated_field(&mut *w, pattern, sep)?;
+                writeln!(w, "{}{}", sep as char, offset)?;
+            }
+        }
+        self.matches += 1;
+        Ok(())
+    }
+
+    /// Writes the number of matches of each pattern and returns the writer.
+    pub fn finish(mut self, patterns: &[Vec<u8>], counts: &[Counter]) -> io::Result<W> {
+        let w = &mut self.w;
+        let totals = patterns.iter().zip(counts);
+        match self.format {
+            Format::Text => {
+                for (pattern, count) in totals {
+                    write!(w, "total ")?;
+                    w.write_all(pattern)?;
+                    writeln!(w, " {}", count)?;
+                }
+            }
+            Format::Json | Format::Ndjson => {
+                if self.format == Format::Json {
+                    write!(w, "],\"counts\":[")?;
+                }
+                for (i, (pattern, count)) in totals.enumerate() {
+                    if self.format == Format::Json && i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(w, "{{\"pattern\":")?;
+                    write_json_string(&mut *w, pattern)?;
+                    write!(w, ",\"count\":{}}}", count)?;
+                    if self.format == Format::Ndjson {
+                        writeln!(w)?;
+                    }
+                }
+                if self.format == Format::Json {
+                    writeln!(w, "]}}")?;
+                }
+            }
+            Format::Csv | Format::Tsv => {
+                for (pattern, count) in totals {
+                    write!(w, "# count ")?;
+                    w.write_all(pattern)?;
+                    writeln!(w, ": {}", count)?;
+                }
+            }
+        }
+        Ok(self.w)
+    }
+}
+
+/// The timings of one benchmarked configuration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BenchResult {
+    pub algorithm: String,
+    /// How the input was read, like `mmap`.
+    pub mode: String,
+    /// The buffer capacity of buffered reads.
+    pub capacity: Option<usize>,
+    /// The time of each repetition.
+    pub times: Vec<Duration>,
+    /// The largest peak resident set size of the repetitions, in bytes, if
+    /// it could be measured.
+    pub peak_rss: Option<u64>,
+}
+
+impl BenchResult {
+    pub fn mean_secs(&self) -> f64 {
+        let sum = self.times.iter().map(Duration::as_secs_f64).sum::<f64>();
+        sum / self.times.len().max(1) as f64
+    }
+
+    /// The sample standard deviation of the times, 0 for a single run.
+    pub fn stddev_secs(&self) -> f64 {
+        if self.times.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean_secs();
+        let squares = self.times.iter().map(|t| (t.as_secs_f64() - mean).powi(2));
+        (squares.sum::<f64>() / (self.times.len() - 1) as f64).sqrt()
+    }
+}
+
+/// The results of the bench subcommand, in the same formats as counts.
+pub struct BenchReport<'a> {
+    /// The input path, or a description of generated input.
+    pub input: String,
+    pub file_size: u64,
+    /// The maximum substring width that was counted.
+    pub digits: usize,
+    /// The number of bytes counted in each repetition, those selected by
+    /// the marker and range.
+    pub input_bytes: u64,
+    /// The number of digits among those bytes.
+    pub input_digits: u64,
+    pub results: &'a [BenchResult],
+}
+
+impl BenchReport<'_> {
+    pub fn write_to(&self, format: Format, mut w: impl Write) -> io::Result<()> {
+        match format {
+            Format::Text => {
+                writeln!(
+                    w,
+                    "input {}: {} bytes, {} counted, {} digits, width {}",
+                    self.input, self.file_size, self.input_bytes, self.input_digits, self.digits
+                )?;
+                writeln!(
+                    w,
+                    "{:<14}{:<10}{:>10}{:>6}{:>12}{:>12}{:>10}{:>14}{:>10}",
+                    "algorithm",
+                    "mode",
+                    "capacity",
+                    "runs",
+                    "mean_secs",
+                    "stddev_secs",
+                    "MB/s",
+                    "digits/s",
+                    "rss_MB"
+                )?;
+                for result in self.results {
+                    let capacity = result.capacity.map_or("-".to_owned(), |c| c.to_string());
+                    let rss = result
+                        .peak_rss
+                        .map_or("-".to_owned(), |rss| format!("{:.1}", rss as f64 / 1e6));
+                    writeln!(
+                        w,
+                        "{:<14}{:<10}{:>10}{:>6}{:>12.4}{:>12.4}{:>10.1}{:>14.0}{:>10}",
+                        result.algorithm,
+                        result.mode,
+                        capacity,
+                        result.times.len(),
+                        result.mean_secs(),
+                        result.stddev_secs(),
+                        self.mb_per_sec(result),
+                        self.digits_per_sec(result),
+                        rss
+                    )?;
+                }
+                Ok(())
+            }
+            Format::Json | Format::Ndjson => {
+                write!(w, "{{\"input\":")?;
+                write_json_string(&mut w, self.input.as_bytes())?;
+                write!(
+                    w,
+                    ",\"file_size\":{},\"digits\":{},\"input_bytes\":{},\"input_digits\":{}",
+                    self.file_size, self.digits, self.input_bytes, self.input_digits
+                )?;
+                if format == Format::Json {
+                    write!(w, ",\"results\":[")?;
+                } else {
+                    writeln!(w, "}}")?;
+                }
+                for (i, result) in self.results.iter().enumerate() {
+                    if format == Format::Json && i != 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(w, "{{")?;
+                    let values = self.values(result, true);
+                    for (j, (name, value)) in BENCH_FIELDS.iter().zip(&values).enumerate() {
+                        if j != 0 {
+                            write!(w, ",")?;
+                        }
+                        write!(w, "\"{}\":{}", name, value)?;
+                    }
+                    write!(w, "}}")?;
+                    if format == Format::Ndjson {
+                        writeln!(w)?;
+                    }
+                }
+                if format == Format::Json {
+                    writeln!(w, "]}}")?;
+                }
+                Ok(())
+            }
+            Format::Csv | Format::Tsv => {
+                let sep = if format == Format::Csv { ',' } else { '\t' };
+                writeln!(w, "# input: {}", self.input)?;
+                writeln!(w, "# file_size: {}", self.file_size)?;
+                writeln!(w, "# digits: {}", self.digits)?;
+                writeln!(w, "# input_bytes: {}", self.input_bytes)?;
+                writeln!(w, "# input_digits: {}", self.input_digits)?;
+                writeln!(w, "{}", BENCH_FIELDS.join(&sep.to_string()))?;
+                for result in self.results {
+                    for (i, value) in self.values(result, false).iter().enumerate() {
+                        if i != 0 {
+                            write!(w, "{}", sep)?;
+                        }
+                        write_separated_field(&mut w, value.as_bytes(), sep as u8)?;
+                    }
+                    writeln!(w)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn mb_per_sec(&self, result: &BenchResult) -> f64 {
+        self.input_bytes as f64 / 1e6 / result.mean_secs()
+    }
+
+    fn digits_per_sec(&self, result: &BenchResult) -> f64 {
+        self.input_digits as f64 / result.mean_secs()
+    }
+
+    /// The values of [`BENCH_FIELDS`] for `result`, as JSON or else as
+    /// plain text for separated values.
+    fn values(&self, result: &BenchResult, json: bool) -> [String; 9] {
+        let times = result.times.iter().map(|t| float(t.as_secs_f64()));
+        let times = times.collect::<Vec<_>>();
+        let (times, string, null) = if json {
+            (format!("[{}]", times.join(",")), "\"", "null")
+        } else {
+            (times.join(" "), "", "")
+        };
+        let optional = |x: Option<u64>| x.map_or(null.to_owned(), |x| x.to_string());
+        [
+            format!("{}{}{}", string, result.algorithm, string),
+            format!("{}{}{}", string, result.mode, string),
+            optional(result.capacity.map(|c| c as u64)),
+            times,
+            float(result.mean_secs()),
+            float(result.stddev_secs()),
+            float(self.mb_per_sec(result)),
+            float(self.digits_per_sec(result)),
+            optional(result.peak_rss),
+        ]
+    }
+}
+
+/// The columns of a [`BenchReport`] in JSON and separated values.
+const BENCH_FIELDS: [&str; 9] = [
+    "algorithm",
+    "mode",
+    "capacity",
+    "times_secs",
+    "mean_secs",
+    "stddev_secs",
+    "mb_per_sec",
+    "digits_per_sec",
+    "peak_rss_bytes",
+];
+
+fn write_json_record(
+    mut w: impl Write,
+    width: usize,
+    substring: &[u8],
+    count: Counter,
+) -> io::Result<()> {
+    write!(w, "{{\"width\":{},\"substring\":", width)?;
+    write_json_string(&mut w, substring)?;
+    write!(w, ",\"count\":{}}}", count)
+}
+
+/// The fields of [`Stats`] as they are written, which are valid JSON
+/// numbers.
+fn stats_fields(stats: &Stats) -> [(&'static str, String); 11] {
+    [
+        ("width", stats.width.to_string()),
+        ("total", stats.total.to_string()),
+        ("chi_squared", float(stats.chi_squared)),
+        ("degrees_of_freedom", float(stats.degrees_of_freedom)),
+        ("p_value", float(stats.p_value)),
+        ("entropy", float(stats.entropy)),
+        ("max_entropy", float(stats.max_entropy)),
+        ("max_count", stats.max_count.to_string()),
+        ("max_deviation", float(stats.max_deviation)),
+        ("min_count", stats.min_count.to_string()),
+        ("min_deviation", float(stats.min_deviation)),
+    ]
+}
+
+/// Formats `x` in scientific notation if it is very small or very large,
+/// instead of spelling out all the zeros.
+fn float(x: f64) -> String {
+    if x == 0.0 || (1e-4..1e16).contains(&x.abs()) {
+        x.to_string()
+    } else {
+        format!("{:e}", x)
+    }
+}
+
+fn write_json_string(mut w: impl Write, s: &[u8]) -> io::Result<()> {
+    write!(w, "\"")?;
+    for c in String::from_utf8_lossy(s).chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\r' => write!(w, "\\r")?,
+            '\t' => write!(w, "\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+/// Writes `field`, quoted if it contains the separator, a quote or a line
+/// break. Digit symbols never need it for the presets.
+fn write_separated_field(mut w: impl Write, field: &[u8], sep: u8) -> io::Result<()> {
+    if !field
+        .iter()
+        .any(|&b| b == sep || b == b'"' || b == b'\n' || b == b'\r')
+    {
+        return w.write_all(field);
+    }
+    w.write_all(b"\"")?;
+    for &b in field {
+        if b == b'"' {
+            w.write_all(b"\"")?;
+        }
+        w.write_all(&[b])?;
+    }
+    w.write_all(b"\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Order;
+
+    fn report<'a>(counts: &'a Counts, rankings: &'a [Ranking]) -> Report<'a> {
+        Report {
+            input: "dir/pi \"hex\".txt".to_owned(),
+            file_size: Some(12),
+            algorithm: "variant-4".to_owned(),
+            elapsed: Duration::from_millis(1500),
+            counts,
+            rankings,
+            missing: &[],
+            stats: &[],
+            first_offsets: None,
+        }
+    }
+
+    fn write(format: Format, counts: &Counts) -> String {
+        let mut buf = Vec::new();
+        report(counts, &[]).write_to(format, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut counts = Counts::new(2);
+        counts.add(b"1", 2);
+        counts.add(b"0", 1);
+        counts.add(b"10", 1);
+
+        assert_eq!(write(Format::Text, &counts), "2 [1, 2]\n1 [1]\n");
+        assert_eq!(
+            write(Format::Json, &counts),
+            "{\"input\":\"dir/pi \\\"hex\\\".txt\",\"file_size\":12,\"digits\":2,\
+             \"algorithm\":\"variant-4\",\"elapsed_secs\":1.5,\"counts\":[\
+             {\"width\":1,\"substring\":\"0\",\"count\":1},\
+             {\"width\":1,\"substring\":\"1\",\"count\":2},\
+             {\"width\":2,\"substring\":\"10\",\"count\":1}]}\n"
+        );
+        assert_eq!(
+            write(Format::Ndjson, &counts),
+            "{\"input\":\"dir/pi \\\"hex\\\".txt\",\"file_size\":12,\"digits\":2,\
+             \"algorithm\":\"variant-4\",\"elapsed_secs\":1.5}\n\
+             {\"width\":1,\"substring\":\"0\",\"count\":1}\n\
+             {\"width\":1,\"substring\":\"1\",\"count\":2}\n\
+             {\"width\":2,\"substring\":\"10\",\"count\":1}\n"
+        );
+        assert_eq!(
+            write(Format::Tsv, &counts),
+            "# input: dir/pi \"hex\".txt\n# file_size: 12\n# digits: 2\n\
+             # algorithm: variant-4\n# elapsed_secs: 1.5\n\
+             width\tsubstring\tcount\n1\t0\t1\n1\t1\t2\n2\t10\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_rankings() {
+        let counts = Counts::new(2);
+        let rankings = [
+            Ranking::new(
+                2,
+                Order::Top,
+                1,
+                vec![(b"12".to_vec(), 5), (b"34".to_vec(), 5)],
+            ),
+            Ranking::new(1, Order::Bottom, 1, vec![(b"0".to_vec(), 1)]),
+        ];
+        let write = |format| {
+            let mut buf = Vec::new();
+            report(&counts, &rankings)
+                .write_rankings_to(format, &mut buf)
+                .unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            write(Format::Text),
+            "top 1 of width 2:\n1 12 5\n1 34 5\nbottom 1 of width 1:\n1 0 1\n"
+        );
+        assert!(write(Format::Json).ends_with(
+            "\"rankings\":[{\"width\":2,\"order\":\"top\",\"k\":1,\"entries\":[\
+             {\"rank\":1,\"substring\":\"12\",\"count\":5},\
+             {\"rank\":1,\"substring\":\"34\",\"count\":5}]},\
+             {\"width\":1,\"order\":\"bottom\",\"k\":1,\"entries\":[\
+             {\"rank\":1,\"substring\":\"0\",\"count\":1}]}]}\n"
+        ));
+        assert!(write(Format::Ndjson).ends_with(
+            "{\"width\":1,\"order\":\"bottom\",\"rank\":1,\"substring\":\"0\",\"count\":1}\n"
+        ));
+        assert!(write(Format::Csv).ends_with(
+            "width,order,rank,substring,count\n2,top,1,12,5\n2,top,1,34,5\n1,bottom,1,0,1\n"
+        ));
+    }
+
+    #[test]
+    fn test_missing() {
+        let counts = Counts::new(2);
+        let missing = [
+            Missing {
+                width: 1,
+                count: Some(0),
+                smallest: vec![],
+            },
+            Missing {
+                width: 2,
+                count: Some(3),
+                smallest: vec![b"02".to_vec(), b"11".to_vec()],
+            },
+        ];
+        let mut report = report(&counts, &[]);
+        report.missing = &missing;
+        let write = |format| {
+            let mut buf = Vec::new();
+            report.write_missing_to(format, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            write(Format::Text),
+            "width 1: 0 missing\nwidth 2: 3 missing\n02\n11\n"
+        );
+        assert!(write(Format::Json).ends_with(
+            "\"missing\":[{\"width\":1,\"count\":0,\"smallest\":null,\"strings\":[]},\
+             {\"width\":2,\"count\":3,\"smallest\":\"02\",\"strings\":[\"02\",\"11\"]}]}\n"
+        ));
+        assert!(write(Format::Ndjson)
+            .ends_with("1.5}\n{\"width\":1,\"count\":0,\"smallest\":null,\"strings\":[]}\n\
+                        {\"width\":2,\"count\":3,\"smallest\":\"02\",\"strings\":[\"02\",\"11\"]}\n"));
+        assert!(
+            write(Format::Tsv).ends_with("width\tmissing\tsubstring\n1\t0\t\n2\t3\t02\n2\t3\t11\n")
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let counts = Counts::new(1);
+        let stats = [Stats {
+            width: 1,
+            total: 20,
+            categories: 2.0,
+            chi_squared: 1.8,
+            degrees_of_freedom: 1.0,
+            p_value: 2.5e-20,
+            entropy: 0.5,
+            max_entropy: 1.0,
+            max_count: 13,
+            max_deviation: 0.3,
+            min_count: 7,
+            min_deviation: -0.3,
+        }];
+        let mut report = report(&counts, &[]);
+        report.stats = &stats;
+        let write = |format| {
+            let mut buf = Vec::new();
+            report.write_stats_to(format, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            write(Format::Text),
+            "width 1: chi-squared 1.8 with 1 degrees of freedom, p-value 2.5e-20\n  \
+             entropy 0.5 of 1 bits, max count 13 (+0.3), min count 7 (-0.3)\n"
+        );
+        assert!(write(Format::Json).ends_with(
+            "\"stats\":[{\"width\":1,\"total\":20,\"chi_squared\":1.8,\
+             \"degrees_of_freedom\":1,\"p_value\":2.5e-20,\"entropy\":0.5,\
+             \"max_entropy\":1,\"max_count\":13,\"max_deviation\":0.3,\
+             \"min_count\":7,\"min_deviation\":-0.3}]}\n"
+        ));
+        assert!(write(Format::Csv).ends_with(
+            "width,total,chi_squared,degrees_of_freedom,p_value,entropy,max_entropy,\
+             max_count,max_deviation,min_count,min_deviation\n\
+             1,20,1.8,1,2.5e-20,0.5,1,13,0.3,7,-0.3\n"
+        ));
+    }
+
+    #[test]
+    fn test_first_offsets() {
+        let mut counts = Counts::new(2);
+        counts.add(b"1", 2);
+        counts.add(b"12", 1);
+        let mut first = FirstOffsets::new(2);
+        first.insert(b"1", 4);
+        let mut report = report(&counts, &[]);
+        report.first_offsets = Some(&first);
+        let write = |format| {
+            let mut buf = Vec::new();
+            report.write_first_offsets_to(format, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(write(Format::Text), "1 2 4\n12 1 -\n");
+        assert!(write(Format::Json).ends_with(
+            "\"first_offsets\":[{\"width\":1,\"substring\":\"1\",\"count\":2,\"first_offset\":4},\
+             {\"width\":2,\"substring\":\"12\",\"count\":1,\"first_offset\":null}]}\n"
+        ));
+        assert!(
+            write(Format::Csv).ends_with("width,substring,count,first_offset\n1,1,2,4\n2,12,1,\n")
+        );
+    }
+
+    #[test]
+    fn test_match_writer() {
+        let patterns = [b"12".to_vec(), b"9".to_vec()];
+        let write = |format| {
+            let mut w = MatchWriter::new(format, Vec::new(), "pi.txt", &patterns).unwrap();
+            w.write_match(b"12", 3).unwrap();
+            w.write_match(b"12", 40).unwrap();
+            String::from_utf8(w.finish(&patterns, &[2, 0]).unwrap()).unwrap()
+        };
+        assert_eq!(write(Format::Text), "3 12\n40 12\ntotal 12 2\ntotal 9 0\n");
+        assert_eq!(
+            write(Format::Json),
+            "{\"input\":\"pi.txt\",\"patterns\":[\"12\",\"9\"],\"matches\":[\
+             {\"pattern\":\"12\",\"offset\":3},{\"pattern\":\"12\",\"offset\":40}],\
+             \"counts\":[{\"pattern\":\"12\",\"count\":2},{\"pattern\":\"9\",\"count\":0}]}\n"
+        );
+        assert_eq!(
+            write(Format::Ndjson),
+            "{\"input\":\"pi.txt\",\"patterns\":[\"12\",\"9\"]}\n\
+             {\"pattern\":\"12\",\"offset\":3}\n{\"pattern\":\"12\",\"offset\":40}\n\
+             {\"pattern\":\"12\",\"count\":2}\n{\"pattern\":\"9\",\"count\":0}\n"
+        );
+        assert_eq!(
+            write(Format::Tsv),
+            "# input: pi.txt\n# patterns: 12 9\npattern\toffset\n12\t3\n12\t40\n\
+             # count 12: 2\n# count 9: 0\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        let mut counts = Counts::new(2);
+        counts.add(b",\"", 3);
+        let csv = write(Format::Csv, &counts);
+        assert!(csv.ends_with("width,substring,count\n2,\",\"\"\",3\n"));
+    }
+
+    #[test]
+    fn test_bench_report() {
+        let results = [BenchResult {
+            algorithm: "variant-4".to_owned(),
+            mode: "unmapped".to_owned(),
+            capacity: Some(8192),
+            times: vec![Duration::from_millis(1500), Duration::from_millis(2500)],
+            peak_rss: None,
+        }];
+        assert!((results[0].mean_secs() - 2.0).abs() < 1e-9);
+        assert!((results[0].stddev_secs() - 0.5f64.sqrt()).abs() < 1e-9);
+        let report = BenchReport {
+            input: "pi.txt".to_owned(),
+            file_size: 4_100_000,
+            digits: 3,
+            input_bytes: 4_000_000,
+            input_digits: 1_000_000,
+            results: &results,
+        };
+        let write = |format| {
+            let mut buf = Vec::new();
+            report.write_to(format, &mut buf).unwrap();
+            String::from_utf8(buf).unwrap()
+        };
+        assert_eq!(
+            write(Format::Json),
+            "{\"input\":\"pi.txt\",\"file_size\":4100000,\"digits\":3,\
+             \"input_bytes\":4000000,\"input_digits\":1000000,\"results\":[{\"algorithm\":\"variant-4\",\
+             \"mode\":\"unmapped\",\"capacity\":8192,\"times_secs\":[1.5,2.5],\
+             \"mean_secs\":2,\"stddev_secs\":0.7071067811865476,\"mb_per_sec\":2,\
+             \"digits_per_sec\":500000,\"peak_rss_bytes\":null}]}\n"
+        );
+        assert!(write(Format::Csv).ends_with(
+            "peak_rss_bytes\nvariant-4,unmapped,8192,1.5 2.5,2,0.7071067811865476,2,500000,\n"
+        ));
+        let text = write(Format::Text);
+        assert!(text
+            .lines()
+            .nth(2)
+            .unwrap()
+            .starts_with("variant-4     unmapped"));
+    }
+
+    #[test]
+    fn test_parse() {
+        for &format in Format::ALL {
+            assert_eq!(format.to_string().parse::<Format>(), Ok(format));
+        }
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Finding all occurrences of a few digit strings in one pass.
+//!
+//! The input is packed into the same rolling register as in `Variant`,
+//! holding the last digits up to the longest pattern. After every digit
+//! the register is truncated to each pattern width and looked up among
+//! the patterns of that width.
+
+use crate::alphabet::{Alphabet, NO_DIGIT};
+use crate::variant::{Number, Radix};
+use crate::{Counter, Error, FastHashMap, Result};
+use std::collections::hash_map::Entry;
+
+/// Finds digit strings in a stream of chunks.
+pub struct Searcher<N = u64> {
+    patterns: Vec<Vec<u8>>,
+    counts: Vec<Counter>,
+    /// Pattern indices by packed number, for each width with patterns.
+    by_width: Vec<(usize, FastHashMap<N, usize>)>,
+    digit_map: [u8; 256],
+    radix: Radix<N>,
+    max_width: usize,
+    current_number: N,
+    current_digits: usize,
+    /// Offset of the next byte.
+    offset: u64,
+}
+
+impl<N: Number> Searcher<N> {
+    /// Creates a searcher for `patterns` of digits of `alphabet`.
+    ///
+    /// Patterns match by digit value, so `AB` and `ab` are the same hex
+    /// pattern. Repeated patterns are only searched once.
+    pub fn new(patterns: &[Vec<u8>], alphabet: &Alphabet) -> Result<Self> {
+        let max_width = patterns.iter().map(|p| p.len()).max().unwrap_or(0);
+        let max_digits = N::max_digits(alphabet.radix());
+        if max_width > max_digits {
+            return Err(Error::UnsupportedWidth {
+                digits: max_width,
+                reason: format!("patterns can have at most {} digits", max_digits),
+            });
+        }
+        let radix = Radix::new(alphabet, max_width);
+
+        let mut unique = Vec::<Vec<u8>>::new();
+        let mut by_width = Vec::<(usize, FastHashMap<N, usize>)>::new();
+        for pattern in patterns {
+            let invalid = |reason| {
+                Error::InvalidArgs(format!(
+                    "pattern {:?} {}",
+                    String::from_utf8_lossy(pattern),
+                    reason
+                ))
+            };
+            if pattern.is_empty() {
+                return Err(invalid("is empty"));
+            }
+            let mut number = N::default();
+            for &b in pattern {
+                let digit = alphabet
+                    .digit(b)
+                    .ok_or_else(|| invalid("contains a non-digit"))?;
+                number = radix.push(number, digit);
+            }
+            let width = pattern.len();
+            let map = match by_width.iter_mut().find(|(w, _)| *w == width) {
+                Some((_, map)) => map,
+                None => {
+                    by_width.push((width, FastHashMap::default()));
+                    &mut by_width.last_mut().unwrap().1
+                }
+            };
+            if let Entry::Vacant(entry) = map.entry(number) {
+                entry.insert(unique.len());
+                unique.push(pattern.clone());
+            }
+        }
+        by_width.sort_by_key(|&(width, _)| width);
+
+        Ok(Self {
+            counts: vec![0; unique.len()],
+            patterns: unique,
+            by_width,
+            digit_map: *alphabet.digit_map(),
+            radix,
+            max_width,
+            current_number: N::default(),
+            current_digits: 0,
+            offset: 0,
+        })
+    }
+
+    /// Starts offsets at `offset` instead of 0. Must be called before the
+    /// first chunk.
+    pub fn start_at(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// The searched patterns, as given but without repetitions.
+    pub fn patterns(&self) -> &[Vec<u8>] {
+        &self.patterns
+    }
+
+    /// The number of matches of each pattern so far.
+    pub fn counts(&self) -> &[Counter] {
+        &self.counts
+    }
+
+    /// Searches `chunk`, which continues the input after all chunks before,
+    /// calling `on_match` with the pattern index and start offset of every
+    /// match. Matches ending at the same byte are reported shortest first.
+    pub fn search<E>(
+        &mut self,
+        chunk: &[u8],
+        mut on_match: impl FnMut(usize, u64) -> Result<(), E>,
+    ) -> Result<(), E> {
+        for &b in chunk {
+            let v = self.digit_map[b as usize];
+            self.offset += 1;
+            if v == NO_DIGIT {
+                self.current_digits = 0;
+                self.current_number = N::default();
+                continue;
+            }
+            if self.current_digits == self.max_width {
+                self.current_digits -= 1;
+                // NB: Keeps the number in range for the next push.
+                self.current_number = self
+                    .radix
+                    .truncate(self.current_number, self.current_digits);
+            }
+            self.current_number = self.radix.push(self.current_number, v);
+            self.current_digits += 1;
+
+            for (width, map) in &self.by_width {
+                if *width > self.current_digits {
+                    break;
+                }
+                let number = self.radix.truncate(self.current_number, *width);
+                if let Some(&i) = map.get(&number) {
+                    self.counts[i] += 1;
+                    on_match(i, self.offset - *width as u64)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search<N: Number>(
+        patterns: &[&str],
+        alphabet: &Alphabet,
+        chunks: &[&[u8]],
+    ) -> Vec<(usize, u64)> {
+        let patterns = patterns
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut searcher = Searcher::<N>::new(&patterns, alphabet).unwrap();
+        searcher.start_at(10);
+        let mut matches = Vec::new();
+        for chunk in chunks {
+            searcher
+                .search(chunk, |i, offset| {
+                    matches.push((i, offset));
+                    Ok::<_, ()>(())
+                })
+                .unwrap();
+        }
+        let mut counts = vec![0; searcher.patterns().len()];
+        for &(i, _) in &matches {
+            counts[i] += 1;
+        }
+        assert_eq!(searcher.counts(), &counts[..]);
+        matches
+    }
+
+    #[test]
+    fn test_search() {
+        let decimal = Alphabet::decimal();
+        let patterns = ["141", "14", "1", "59265", "14"];
+        let input: &[&[u8]] = &[b"1415926", b"5_1414\n1"];
+        let expected = vec![
+            (2, 10),
+            (1, 10),
+            (2, 12),
+            (0, 10),
+            (3, 13),
+            (2, 19),
+            (1, 19),
+            (2, 21),
+            (0, 19),
+            (1, 21),
+            (2, 24),
+        ];
+        assert_eq!(search::<u64>(&patterns, &decimal, input), expected);
+        assert_eq!(
+            search::<u128>(&patterns, &decimal, &[&input.concat()]),
+            expected
+        );
+
+        let hex = Alphabet::hex();
+        assert_eq!(
+            search::<u64>(&["aB"], &hex, &[b"ab_AB0aB"]),
+            vec![(0, 10), (0, 13), (0, 16)]
+        );
+        assert_eq!(
+            search::<u64>(&["ff"], &hex, &[b"f", b"ff"]),
+            vec![(0, 10), (0, 11)]
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let decimal = Alphabet::decimal();
+        assert!(Searcher::<u64>::new(&[b"12a".to_vec()], &decimal).is_err());
+        assert!(Searcher::<u64>::new(&[Vec::new()], &decimal).is_err());
+        assert!(Searcher::<u64>::new(&[vec![b'1'; 20]], &decimal).is_err());
+        assert!(Searcher::<u128>::new(&[vec![b'1'; 20]], &decimal).is_ok());
+    }
+}
@@ -0,0 +1,391 @@
+//! Selection of the part of an input that is counted.
+//!
+//! By default counting starts after the first `.`, which skips the `3.` of
+//! files like `3.14159...`. The marker can be any byte string or none, and
+//! the bytes after it can be narrowed down to a range or to the first few
+//! digits.
+
+use crate::alphabet::{Alphabet, NO_DIGIT};
+use std::fmt;
+use std::str::FromStr;
+
+/// A window of byte offsets, `start..end` or `start..` to the end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..", self.start)?;
+        if let Some(end) = self.end {
+            write!(f, "{}", end)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ByteRange {
+    type Err = String;
+
+    /// Parses `START..END`, where either side may be left out.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid range {:?}, expected START..END", s);
+        let (start, end) = s.split_once("..").ok_or_else(invalid)?;
+        let start = match start {
+            "" => 0,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().map_err(|_| invalid())?),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(format!("range {:?} ends before it starts", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Which bytes of an input are counted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Counting starts after the first occurrence of this, or at the
+    /// start of the input if `None`. Nothing is counted if it never occurs.
+    pub marker: Option<Vec<u8>>,
+    /// Offsets of the counted bytes, relative to the end of the marker.
+    pub range: ByteRange,
+    /// Counting stops after this many digits.
+    pub max_digits: Option<u64>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            marker: Some(b".".to_vec()),
+            range: ByteRange::default(),
+            max_digits: None,
+        }
+    }
+}
+
+impl Selection {
+    /// Returns the counted part of a complete input.
+    pub fn select<'a>(&self, bytes: &'a [u8], alphabet: &Alphabet) -> &'a [u8] {
+        let mut selector = Selector::new(self, alphabet);
+        let mut offset = 0;
+        let mut counted = None;
+        while offset < bytes.len() {
+            match selector.next(&bytes[offset..]) {
+                Step::Skip(n) => offset += n,
+                Step::Count(n) => {
+                    let start = counted.map_or(offset, |(start, _)| start);
+                    offset += n;
+                    counted = Some((start, offset));
+                }
+                Step::Done => break,
+            }
+        }
+        counted.map_or(&[][..], |(start, end)| &bytes[start..end])
+    }
+}
+
+/// Parses a marker given on the command line.
+///
+/// Understands the escapes `\n`, `\r`, `\t`, `\\` and `\xHH`. An empty
+/// marker is none.
+pub fn parse_marker(s: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut marker = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            marker.push(b);
+            continue;
+        }
+        marker.push(match bytes.next() {
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'\\') => b'\\',
+            Some(b'x') => {
+                let hex = [bytes.next(), bytes.next()];
+                let hex = match hex {
+                    [Some(a), Some(b)] => [a, b],
+                    _ => return Err(format!("incomplete \\x escape in marker {:?}", s)),
+                };
+                std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("invalid \\x escape in marker {:?}", s))?
+            }
+            _ => return Err(format!("unknown escape in marker {:?}", s)),
+        });
+    }
+    Ok(if marker.is_empty() {
+        None
+    } else {
+        Some(marker)
+    })
+}
+
+/// Finds the first occurrence of a marker in a stream of chunks, with
+/// Knuth-Morris-Pratt so matches may span chunks.
+#[derive(Clone, Debug)]
+struct MarkerSearch {
+    marker: Vec<u8>,
+    /// Length of the longest proper prefix of `marker[..=i]` that is also
+    /// a suffix of it.
+    failure: Vec<usize>,
+    matched: usize,
+}
+
+impl MarkerSearch {
+    fn new(marker: &[u8]) -> Self {
+        assert!(!marker.is_empty());
+        let mut failure = vec![0; marker.len()];
+        let mut k = 0;
+        for i in 1..marker.len() {
+            while k > 0 && marker[i] != marker[k] {
+                k = failure[k - 1];
+            }
+            if marker[i] == marker[k] {
+                k += 1;
+            }
+            failure[i] = k;
+        }
+        Self {
+            marker: marker.to_vec(),
+            failure,
+            matched: 0,
+        }
+    }
+
+    /// Returns the offset just after the marker if it ends in `chunk`.
+    fn find(&mut self, chunk: &[u8]) -> Option<usize> {
+        for (i, &b) in chunk.iter().enumerate() {
+            while self.matched > 0 && b != self.marker[self.matched] {
+                self.matched = self.failure[self.matched - 1];
+            }
+            if b == self.marker[self.matched] {
+                self.matched += 1;
+            }
+            if self.matched == self.marker.len() {
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+}
+
+/// What to do with the start of the next input chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Drop this many bytes.
+    Skip(usize),
+    /// Count this many bytes.
+    Count(usize),
+    /// Nothing more is counted.
+    Done,
+}
+
+/// Applies a [`Selection`] to an input that arrives in chunks.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    search: Option<MarkerSearch>,
+    range: ByteRange,
+    max_digits: Option<u64>,
+    digit_map: [u8; 256],
+    /// Offset after the marker.
+    position: u64,
+    /// Offset of the first counted byte.
+    begin: u64,
+    /// Digits in `range.start..position`.
+    digits: u64,
+}
+
+impl Selector {
+    pub fn new(selection: &Selection, alphabet: &Alphabet) -> Self {
+        Self {
+            search: selection
+                .marker
+                .as_deref()
+                .filter(|marker| !marker.is_empty())
+                .map(MarkerSearch::new),
+            range: selection.range,
+            max_digits: selection.max_digits,
+            digit_map: *alphabet.digit_map(),
+            position: 0,
+            begin: selection.range.start,
+            digits: 0,
+        }
+    }
+
+    /// Skips the bytes before `position`, which were already counted
+    /// before a checkpoint.
+    pub fn resume_at(&mut self, position: u64) {
+        self.begin = self.begin.max(position);
+    }
+
+    /// The offset after the marker of the next byte.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Decides about the start of `chunk`, which continues the input
+    /// after all bytes skipped or counted before.
+    pub fn next(&mut self, chunk: &[u8]) -> Step {
+        if let Some(search) = &mut self.search {
+            return match search.find(chunk) {
+                Some(n) => {
+                    self.search = None;
+                    Step::Skip(n)
+                }
+                None => Step::Skip(chunk.len()),
+            };
+        }
+
+        let len = chunk.len() as u64;
+        if self.position < self.begin {
+            let n = len.min(self.begin - self.position);
+            if self.max_digits.is_some() && self.position + n > self.range.start {
+                let from = self.range.start.saturating_sub(self.position);
+                self.digits += self.count_digits(&chunk[from as usize..n as usize]);
+            }
+            self.position += n;
+            return Step::Skip(n as usize);
+        }
+
+        let mut n = len;
+        if let Some(end) = self.range.end {
+            if self.position >= end {
+                return Step::Done;
+            }
+            n = n.min(end - self.position);
+        }
+        if let Some(max_digits) = self.max_digits {
+            if self.digits >= max_digits {
+                return Step::Done;
+            }
+            let mut cut = 0;
+            for &b in &chunk[..n as usize] {
+                cut += 1;
+                if self.digit_map[b as usize] != NO_DIGIT {
+                    self.digits += 1;
+                    if self.digits == max_digits {
+                        break;
+                    }
+                }
+            }
+            n = cut;
+        }
+        self.position += n;
+        Step::Count(n as usize)
+    }
+
+    fn count_digits(&self, bytes: &[u8]) -> u64 {
+        let digits = bytes
+            .iter()
+            .filter(|&&b| self.digit_map[b as usize] != NO_DIGIT);
+        digits.count() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(marker: &str, range: &str, max_digits: Option<u64>) -> Selection {
+        Selection {
+            marker: parse_marker(marker).unwrap(),
+            range: range.parse().unwrap(),
+            max_digits,
+        }
+    }
+
+    /// Feeds `input` in chunks of `chunk_len` bytes, resuming at `resume`.
+    fn select_chunked(s: &Selection, input: &[u8], chunk_len: usize, resume: u64) -> Vec<u8> {
+        let mut selector = Selector::new(s, &Alphabet::decimal());
+        selector.resume_at(resume);
+        let mut out = Vec::new();
+        let mut offset = 0;
+        while offset < input.len() {
+            let end = input.len().min(offset + chunk_len);
+            match selector.next(&input[offset..end]) {
+                Step::Skip(n) => offset += n,
+                Step::Count(n) => {
+                    out.extend_from_slice(&input[offset..offset + n]);
+                    offset += n;
+                }
+                Step::Done => break,
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_select() {
+        let input = b"header\r\n3.1415_9265\r\n35";
+        let cases: &[(&str, &str, Option<u64>, &[u8])] = &[
+            (".", "..", None, b"1415_9265\r\n35"),
+            ("", "..", None, input),
+            ("\\r\\n", "..", None, b"3.1415_9265\r\n35"),
+            ("\\x0d\\x0a3", "2..6", None, b"415_"),
+            (".", "3..", Some(5), b"5_9265"),
+            (".", "..", Some(0), b""),
+            (".", "..100", Some(100), b"1415_9265\r\n35"),
+            ("5\r\n4", "..", None, b""),
+        ];
+        for &(marker, range, max_digits, expected) in cases {
+            let s = selection(marker, range, max_digits);
+            assert_eq!(s.select(input, &Alphabet::decimal()), expected, "{:?}", s);
+            for chunk_len in 1..5 {
+                assert_eq!(select_chunked(&s, input, chunk_len, 0), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let input = b"3.14159265358979323846";
+        let s = selection(".", "2..", Some(10));
+        let all = s.select(input, &Alphabet::decimal());
+        assert_eq!(all, b"1592653589");
+        for resume in 0..input.len() as u64 {
+            let skipped = (resume as usize).saturating_sub(2).min(all.len());
+            assert_eq!(
+                select_chunked(&s, input, 3, resume),
+                &all[skipped..],
+                "resume={}",
+                resume
+            );
+        }
+    }
+
+    #[test]
+    fn test_marker_across_chunks() {
+        let mut search = MarkerSearch::new(b"aab");
+        assert_eq!(search.find(b"xaa"), None);
+        assert_eq!(search.find(b"aab1"), Some(3));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            "5..".parse::<ByteRange>(),
+            Ok(ByteRange {
+                start: 5,
+                end: None
+            })
+        );
+        assert_eq!("..7".parse::<ByteRange>().unwrap().to_string(), "0..7");
+        assert!("7..5".parse::<ByteRange>().is_err());
+        assert!("7".parse::<ByteRange>().is_err());
+        assert_eq!(
+            parse_marker("a\\x00\\\\").unwrap(),
+            Some(b"a\x00\\".to_vec())
+        );
+        assert!(parse_marker("\\x0").is_err());
+        assert!(parse_marker("\\q").is_err());
+    }
+}
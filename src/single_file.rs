@@ -1,5 +1,6 @@
+use count_digits::Counts;
 use path_abs::{PathAbs, PathInfo};
-use std::io::Write;
+use std::io::{self, Write};
 use std::{path::PathBuf, time::Instant};
 use structopt::StructOpt;
 
@@ -56,7 +57,7 @@ struct CliOptions {
     digit: usize,
 }
 
-struct Variant {
+pub(crate) struct Variant {
     map: Vec<Vec<Counter>>,
     digits: usize,
     current_hexstring: HexString,
@@ -64,7 +65,7 @@ struct Variant {
 }
 
 impl Variant {
-    fn new(digits: usize) -> Self {
+    pub(crate) fn new(digits: usize) -> Self {
         assert!(std::mem::size_of::<HexString>() * 2 >= digits);
         assert!(std::mem::size_of::<HexString>() <= std::mem::size_of::<usize>());
 
@@ -97,7 +98,7 @@ impl Variant {
         }
     }
 
-    fn count_digit(&mut self, byte: u8) {
+    pub(crate) fn count_digit(&mut self, byte: u8) {
         let v = DIGIT_MAP[byte as usize];
 
         if v == 0xff {
@@ -122,7 +123,7 @@ impl Variant {
         }
     }
 
-    fn finalize(&mut self) {
+    pub(crate) fn finalize(&mut self) {
         self.end_of_hexstring_run();
         self.do_late_counts();
     }
@@ -158,6 +159,20 @@ impl Variant {
             .map(|(k, v)| (k as HexString, v))
     }
 
+    /// The counts keyed by lower case hex strings, like those of the
+    /// library algorithms.
+    pub(crate) fn into_count(self) -> Counts {
+        let mut count = Counts::new(self.digits);
+        for (width, counts) in self.map.iter().enumerate().skip(1) {
+            for (number, n) in Self::hexstring_counts(counts) {
+                if n != 0 {
+                    count.add(format!("{:01$x}", number, width).as_bytes(), n);
+                }
+            }
+        }
+        count
+    }
+
     fn do_late_counts(&mut self) {
         for digits in (2..(self.digits + 1)).rev() {
             let (current, prev) = self.counter_for_width_and_prev_width(digits);
@@ -187,9 +202,9 @@ fn main_loop(
     now
 }
 
-pub fn main() {
+pub fn main() -> io::Result<()> {
     let opt = CliOptions::from_args();
-    let path = PathAbs::new(opt.file).unwrap();
+    let path = PathAbs::new(opt.file)?;
     let digit = opt.digit;
     let mut imp = Variant::new(digit);
     let mut cnt = 0;
@@ -207,12 +222,10 @@ pub fn main() {
         }
     };
 
-    let filestream = match std::fs::File::open(&path) {
-        Ok(file) => file,
-        Err(error) => panic!("{}\n{}", error, ERRMSG),
-    };
+    let filestream = std::fs::File::open(&path)
+        .map_err(|error| io::Error::new(error.kind(), format!("{}\n{}", error, ERRMSG)))?;
     let now = {
-        let memmap = unsafe { memmap::Mmap::map(&filestream).unwrap() };
+        let memmap = unsafe { memmap::Mmap::map(&filestream)? };
         let iter = memmap.iter().copied();
         main_loop(&mut imp, iter, do_count)
     };
@@ -222,19 +235,19 @@ pub fn main() {
         cntp * INTERVAL + cnt,
         now.elapsed().as_secs_f64()
     );
+    let invalid_path = || io::Error::new(io::ErrorKind::InvalidInput, "invalid input path");
     let outpath = PathAbs::new(format!(
         "{}/{}_result.txt",
-        path.parent().unwrap().display(),
-        path.file_stem().unwrap().to_str().unwrap()
-    ))
-    .unwrap();
+        path.parent()?.display(),
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(invalid_path)?
+    ))?;
     println!("Output Path: {}", outpath.display());
-    let mut output = match std::fs::File::create(&outpath) {
-        Ok(file) => file,
-        Err(error) => panic!("{}", error),
-    };
+    let mut output = std::fs::File::create(&outpath)?;
     for vec in &imp.map {
         let filter = vec.iter().filter(|v| **v > 0).collect::<Vec<_>>();
-        write!(output, "{} {:?}\n", filter.len(), filter).unwrap();
+        writeln!(output, "{} {:?}", filter.len(), filter)?;
     }
+    Ok(())
 }
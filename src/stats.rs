@@ -0,0 +1,287 @@
+//! Goodness of fit of the counts of each width against uniform digits.
+//!
+//! In a normal number every string of `width` digits is equally likely,
+//! so each of the `radix^width` strings is expected `total / radix^width`
+//! times. The chi-squared statistic measures how far the counts are from
+//! that, and the p-value is the chance of an at least as large statistic
+//! with truly uniform digits.
+
+use crate::{Counter, Counts};
+
+/// Above this many degrees of freedom, p-values use the Wilson-Hilferty
+/// normal approximation instead of the incomplete gamma function.
+const EXACT_MAX_DOF: f64 = 1e4;
+
+/// Statistics of the counts of one width.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub width: usize,
+    /// Number of counted strings of this width.
+    pub total: Counter,
+    /// Number of possible strings, `radix^width`.
+    pub categories: f64,
+    pub chi_squared: f64,
+    pub degrees_of_freedom: f64,
+    /// Upper tail probability of `chi_squared`.
+    pub p_value: f64,
+    /// Shannon entropy of the string frequencies, in bits.
+    pub entropy: f64,
+    /// The entropy of uniform frequencies, `width * log2(radix)`.
+    pub max_entropy: f64,
+    pub max_count: Counter,
+    /// `(max_count - expected) / expected`
+    pub max_deviation: f64,
+    /// Zero if some string never occurred.
+    pub min_count: Counter,
+    /// `(min_count - expected) / expected`
+    pub min_deviation: f64,
+}
+
+/// Collects the sums needed for [`Stats`] in one pass over the counts.
+#[derive(Default)]
+pub(crate) struct StatsBuilder {
+    total: Counter,
+    present: u64,
+    sum_squares: f64,
+    sum_log: f64,
+    max: Counter,
+    min: Option<Counter>,
+}
+
+impl StatsBuilder {
+    pub(crate) fn add(&mut self, count: Counter) {
+        if count == 0 {
+            return;
+        }
+        let c = count as f64;
+        self.total += count;
+        self.present += 1;
+        self.sum_squares += c * c;
+        self.sum_log += c * c.log2();
+        self.max = self.max.max(count);
+        self.min = Some(self.min.map_or(count, |min| min.min(count)));
+    }
+
+    pub(crate) fn finish(self, width: usize, radix: usize) -> Stats {
+        let categories = (radix as f64).powi(width as i32);
+        let max_entropy = width as f64 * (radix as f64).log2();
+        let degrees_of_freedom = categories - 1.0;
+        let min_count = if (self.present as f64) < categories {
+            0
+        } else {
+            self.min.unwrap_or(0)
+        };
+        if self.total == 0 {
+            return Stats {
+                width,
+                total: 0,
+                categories,
+                chi_squared: 0.0,
+                degrees_of_freedom,
+                p_value: 1.0,
+                entropy: 0.0,
+                max_entropy,
+                max_count: 0,
+                max_deviation: 0.0,
+                min_count: 0,
+                min_deviation: 0.0,
+            };
+        }
+
+        let total = self.total as f64;
+        let expected = total / categories;
+        // NB: The sum of `(o - e)^2 / e` over all strings, including the
+        // ones that never occurred, simplifies to `sum(o^2) / e - total`.
+        let chi_squared = (self.sum_squares / expected - total).max(0.0);
+        Stats {
+            width,
+            total: self.total,
+            categories,
+            chi_squared,
+            degrees_of_freedom,
+            p_value: chi_squared_p_value(chi_squared, degrees_of_freedom),
+            entropy: (total.log2() - self.sum_log / total).max(0.0),
+            max_entropy,
+            max_count: self.max,
+            max_deviation: (self.max as f64 - expected) / expected,
+            min_count,
+            min_deviation: (min_count as f64 - expected) / expected,
+        }
+    }
+}
+
+impl Stats {
+    /// Computes the statistics of width `width` from `counts`.
+    pub fn from_counts(counts: &Counts, radix: usize, width: usize) -> Self {
+        let mut builder = StatsBuilder::default();
+        for (substring, count) in counts.iter() {
+            if substring.len() == width {
+                builder.add(count);
+            }
+        }
+        builder.finish(width, radix)
+    }
+}
+
+/// The probability that a chi-squared distributed variable with `dof`
+/// degrees of freedom is at least `x`.
+fn chi_squared_p_value(x: f64, dof: f64) -> f64 {
+    if dof <= 0.0 {
+        return 1.0;
+    }
+    if dof > EXACT_MAX_DOF {
+        let v = 2.0 / (9.0 * dof);
+        let z = ((x / dof).cbrt() - (1.0 - v)) / v.sqrt();
+        return 0.5 * erfc(z / std::f64::consts::SQRT_2);
+    }
+    gamma_q(dof / 2.0, x / 2.0)
+}
+
+/// The regularized upper incomplete gamma function `Q(a, x)`.
+fn gamma_q(a: f64, x: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 100_000;
+    const EPS: f64 = 1e-15;
+    const TINY: f64 = 1e-300;
+
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        // Series of the lower function P
+        let mut ap = a;
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        for _ in 0..MAX_ITERATIONS {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        (1.0 - sum * prefactor).max(0.0)
+    } else {
+        // Continued fraction of Q, with the modified Lentz method
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / TINY;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < TINY {
+                d = TINY;
+            }
+            c = b + an / c;
+            if c.abs() < TINY {
+                c = TINY;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPS {
+                break;
+            }
+        }
+        (prefactor * h).min(1.0)
+    }
+}
+
+/// `ln(Gamma(x))` for `x > 0`, with the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (i, &c) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += c / (x + i as f64);
+    }
+    let t = x + G + 0.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// The complementary error function, with a relative error below 1.2e-7.
+fn erfc(z: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * z.abs());
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if z >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_p_value() {
+        assert_close(chi_squared_p_value(3.841_458_8, 1.0), 0.05, 1e-6);
+        assert_close(chi_squared_p_value(18.307_038, 10.0), 0.05, 1e-6);
+        assert_close(chi_squared_p_value(2.0, 2.0), (-1.0f64).exp(), 1e-12);
+        assert_close(chi_squared_p_value(0.0, 5.0), 1.0, 1e-12);
+        assert_close(chi_squared_p_value(1e6, 10.0), 0.0, 1e-12);
+        // Right above the switch to the approximation
+        let dof = EXACT_MAX_DOF + 1.0;
+        for &x in &[dof - 300.0, dof, dof + 150.0] {
+            let exact = gamma_q(dof / 2.0, x / 2.0);
+            assert_close(chi_squared_p_value(x, dof), exact, 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut counts = Counts::new(2);
+        for &(s, c) in &[("0", 10), ("1", 10), ("00", 2), ("01", 6), ("10", 12)] {
+            counts.add(s.as_bytes(), c);
+        }
+        let uniform = Stats::from_counts(&counts, 2, 1);
+        assert_eq!((uniform.total, uniform.chi_squared), (20, 0.0));
+        assert_close(uniform.p_value, 1.0, 1e-12);
+        assert_close(uniform.entropy, 1.0, 1e-12);
+        assert_eq!((uniform.min_count, uniform.max_deviation), (10, 0.0));
+
+        // Expected 5 each: (9 + 1 + 49 + 25) / 5
+        let skewed = Stats::from_counts(&counts, 2, 2);
+        assert_close(skewed.chi_squared, 16.8, 1e-9);
+        assert_eq!(skewed.degrees_of_freedom, 3.0);
+        assert_close(skewed.p_value, 0.000_777_4, 1e-6);
+        assert_eq!((skewed.max_count, skewed.min_count), (12, 0));
+        assert_close(skewed.max_deviation, 1.4, 1e-12);
+        assert_close(skewed.min_deviation, -1.0, 1e-12);
+        assert_eq!(skewed.max_entropy, 2.0);
+
+        let empty = Stats::from_counts(&counts, 2, 3);
+        assert_eq!((empty.total, empty.p_value), (0, 1.0));
+    }
+}
@@ -1,43 +1,165 @@
-use crate::{Counter, FastHashMap, Process};
-
-const DIGIT_MAP: &[u8; 256] = &[
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 255, 255, 255,
-    255, 255, 255, 255, 10, 11, 12, 13, 14, 15, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 10, 11, 12, 13,
-    14, 15, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255,
-    255, 255, 255,
-];
-
-const HEX_MASKS: &[Number; 17] = &[
-    0x0000000000000000,
-    0x000000000000000f,
-    0x00000000000000ff,
-    0x0000000000000fff,
-    0x000000000000ffff,
-    0x00000000000fffff,
-    0x0000000000ffffff,
-    0x000000000fffffff,
-    0x00000000ffffffff,
-    0x0000000fffffffff,
-    0x000000ffffffffff,
-    0x00000fffffffffff,
-    0x0000ffffffffffff,
-    0x000fffffffffffff,
-    0x00ffffffffffffff,
-    0x0fffffffffffffff,
-    0xffffffffffffffff,
-];
-
-type Number = u64;
+use crate::alphabet::{Alphabet, NO_DIGIT};
+use crate::checkpoint::{self, read_u128, read_u64, write_u128, write_u64};
+use crate::classify::{Classifier, BLOCK};
+use crate::missing::{self, Missing};
+use crate::ranking::{self, Order, Ranking};
+use crate::stats::{Stats, StatsBuilder};
+use crate::{Counter, Counts, FastHashMap, FirstOffsets, Process};
+use std::convert::{TryFrom, TryInto};
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::ops::{Add, BitAnd, BitOr, Div, Mul, Rem, Shl, Shr};
+
+/// An unsigned integer holding a digit string as a base `radix` number,
+/// with the last digit as the least significant one.
+pub trait Number:
+    'static
+    + Copy
+    + Default
+    + Eq
+    + Ord
+    + Hash
+    + Send
+    + From<u8>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Add<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Rem<Output = Self>
+{
+    const BITS: u32;
+
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+    fn to_index(self) -> usize;
+    fn from_index(index: usize) -> Self;
+    fn to_u128(self) -> u128;
+    /// `None` if `v` does not fit.
+    fn try_from_u128(v: u128) -> Option<Self>;
+
+    /// The most base `radix` digits that fit into one number.
+    fn max_digits(radix: usize) -> usize {
+        if radix.is_power_of_two() {
+            return (Self::BITS / radix.trailing_zeros()) as usize;
+        }
+        let radix = Self::from_index(radix);
+        let mut power = Self::from(1);
+        let mut digits = 0;
+        while let Some(next) = power.checked_mul(radix) {
+            power = next;
+            digits += 1;
+        }
+        digits
+    }
+}
+
+macro_rules! impl_number {
+    ($t:ty) => {
+        impl Number for $t {
+            const BITS: u32 = <$t>::BITS;
+
+            fn checked_mul(self, rhs: Self) -> Option<Self> {
+                <$t>::checked_mul(self, rhs)
+            }
+            fn wrapping_mul(self, rhs: Self) -> Self {
+                <$t>::wrapping_mul(self, rhs)
+            }
+            fn wrapping_sub(self, rhs: Self) -> Self {
+                <$t>::wrapping_sub(self, rhs)
+            }
+            fn to_index(self) -> usize {
+                self as usize
+            }
+            fn from_index(index: usize) -> Self {
+                index as Self
+            }
+            fn to_u128(self) -> u128 {
+                self as u128
+            }
+            fn try_from_u128(v: u128) -> Option<Self> {
+                <$t>::try_from(v).ok()
+            }
+        }
+    };
+}
+
+impl_number!(u64);
+impl_number!(u128);
+
+/// Packs digit strings into a `Number` for a given radix.
+///
+/// For power of two radices this is plain bit packing with log2(radix)
+/// bits per digit, which avoids the multiplications and divisions.
+#[derive(Clone)]
+pub(crate) struct Radix<N> {
+    radix: N,
+    shift: Option<u32>,
+    /// `radix^width` for every width. Wraps around to 0 if that is exactly
+    /// `2^BITS`, which `truncate` relies on.
+    powers: Vec<N>,
+}
+
+impl<N: Number> Radix<N> {
+    pub(crate) fn new(alphabet: &Alphabet, digits: usize) -> Self {
+        let radix = N::from_index(alphabet.radix());
+        let mut powers = vec![N::from(1)];
+        for _ in 0..digits {
+            powers.push(powers.last().unwrap().wrapping_mul(radix));
+        }
+        Self {
+            radix,
+            shift: alphabet.bits_per_digit(),
+            powers,
+        }
+    }
+
+    /// Appends `digit` to the string `n`.
+    ///
+    /// `n` needs to have less than `N::max_digits` digits.
+    pub(crate) fn push(&self, n: N, digit: u8) -> N {
+        match self.shift {
+            Some(shift) => (n << shift) | N::from(digit),
+            None => n * self.radix + N::from(digit),
+        }
+    }
+
+    /// Keeps the last `width` digits of `n`.
+    pub(crate) fn truncate(&self, n: N, width: usize) -> N {
+        match self.shift {
+            Some(_) => n & self.powers[width].wrapping_sub(N::from(1)),
+            None => n % self.powers[width],
+        }
+    }
+
+    /// The largest string of `width` digits.
+    pub(crate) fn last(&self, width: usize) -> N {
+        self.powers[width].wrapping_sub(N::from(1))
+    }
+
+    /// Drops the last digit of `n`.
+    pub(crate) fn prefix(&self, n: N) -> N {
+        match self.shift {
+            Some(shift) => n >> shift,
+            None => n / self.radix,
+        }
+    }
+
+    /// Renders the `width` digit string `n` with the symbols of `alphabet`.
+    pub(crate) fn render(&self, mut n: N, width: usize, alphabet: &Alphabet) -> Vec<u8> {
+        let mut vec = vec![0; width];
+        for c in vec.iter_mut().rev() {
+            let digit = (n % self.radix).to_index() as u8;
+            *c = alphabet.symbol(digit);
+            n = self.prefix(n);
+        }
+        vec
+    }
+}
 
 pub trait CountStrategy: Default {
     const COUNT_LATE: bool;
@@ -55,36 +177,75 @@ impl CountStrategy for LateCount {
     const COUNT_LATE: bool = true;
 }
 
-pub trait CounterStorage<'a> {
-    type ForWidth: CounterForWidth<'a>;
+pub trait CounterStorage<'a, N: Number = u64> {
+    type ForWidth: CounterForWidth<'a, N>;
 
-    fn new(digits: usize) -> Self;
+    /// Creates storage for strings of up to `digits` base `radix` digits.
+    fn new(digits: usize, radix: usize) -> Self;
+    /// Adds all counts of `other` to `self`.
+    fn merge(&mut self, other: Self);
+    /// Keeps the smaller non-zero entry of `self` and `other` for every
+    /// number, for tables of first offsets.
+    fn merge_min(&mut self, other: Self);
     fn width_and_prev_width(&'a mut self, width: usize) -> (Self::ForWidth, Self::ForWidth);
     fn width(&'a mut self, width: usize) -> Self::ForWidth {
         self.width_and_prev_width(width).0
     }
 }
 
-pub trait CounterForWidth<'a> {
-    fn for_each(&self, f: impl FnMut(Number, Counter));
-    fn count_number(&mut self, v: Number, delta: u64);
+pub trait CounterForWidth<'a, N: Number = u64> {
+    fn for_each(&self, f: impl FnMut(N, Counter));
+    fn count_number(&mut self, v: N, delta: u64);
+    /// Sets the entry of `v` to `value`, unless it already has a smaller
+    /// non-zero one.
+    fn keep_min(&mut self, v: N, value: Counter);
+
+    /// Calls `f` with every number up to `last` that was never counted, in
+    /// increasing order, until it returns `false`.
+    fn for_each_missing(&self, last: N, f: impl FnMut(N) -> bool) {
+        let mut present = Vec::new();
+        self.for_each(|number, count| {
+            if count != 0 {
+                present.push(number);
+            }
+        });
+        present.sort_unstable();
+        missing::for_each_gap(&present, last, f);
+    }
 }
 
-pub struct HashMapCounter {
-    count_maps: Vec<FastHashMap<Number, Counter>>,
+pub struct HashMapCounter<N = u64> {
+    count_maps: Vec<FastHashMap<N, Counter>>,
     digits: usize,
 }
 
-impl<'a> CounterStorage<'a> for HashMapCounter {
-    type ForWidth = HashMapCounterWidth<'a>;
+impl<'a, N: Number> CounterStorage<'a, N> for HashMapCounter<N> {
+    type ForWidth = HashMapCounterWidth<'a, N>;
 
-    fn new(digits: usize) -> Self {
+    fn new(digits: usize, _radix: usize) -> Self {
         let mut count_maps = Vec::new();
         for _number_width in 0..(digits + 1) {
             count_maps.push(FastHashMap::default());
         }
         Self { count_maps, digits }
     }
+    fn merge(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for (k, v) in other_map {
+                *map.entry(k).or_default() += v;
+            }
+        }
+    }
+    fn merge_min(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for (k, v) in other_map {
+                let entry = map.entry(k).or_insert(v);
+                *entry = v.min(*entry);
+            }
+        }
+    }
     fn width_and_prev_width(&'a mut self, width: usize) -> (Self::ForWidth, Self::ForWidth) {
         let (prev, current) = self.count_maps.split_at_mut(width);
         let current = current.first_mut().unwrap();
@@ -92,7 +253,7 @@ impl<'a> CounterStorage<'a> for HashMapCounter {
         (
             HashMapCounterWidth {
                 digits: self.digits,
-                width: width,
+                width,
                 map: current,
             },
             HashMapCounterWidth {
@@ -104,40 +265,67 @@ impl<'a> CounterStorage<'a> for HashMapCounter {
     }
 }
 
-pub struct HashMapCounterWidth<'a> {
-    map: &'a mut FastHashMap<Number, Counter>,
+pub struct HashMapCounterWidth<'a, N> {
+    map: &'a mut FastHashMap<N, Counter>,
     digits: usize,
     width: usize,
 }
 
-impl<'a> CounterForWidth<'a> for HashMapCounterWidth<'a> {
-    fn for_each(&self, mut f: impl FnMut(Number, Counter)) {
+impl<'a, N: Number> CounterForWidth<'a, N> for HashMapCounterWidth<'a, N> {
+    fn for_each(&self, mut f: impl FnMut(N, Counter)) {
         for (k, v) in self.map.iter() {
             f(*k, *v);
         }
     }
-    fn count_number(&mut self, v: Number, delta: u64) {
+    fn count_number(&mut self, v: N, delta: u64) {
         *self.map.entry(v).or_default() += delta;
         print_count_number_single_masked(v, 1, self.width, self.digits);
     }
+    fn keep_min(&mut self, v: N, value: Counter) {
+        let entry = self.map.entry(v).or_insert(value);
+        *entry = value.min(*entry);
+    }
 }
 
-pub struct VecCounter {
+pub struct VecCounter<N = u64> {
     count_maps: Vec<Vec<Counter>>,
     digits: usize,
+    _number: PhantomData<N>,
 }
 
-impl<'a> CounterStorage<'a> for VecCounter {
-    type ForWidth = VecCounterWidth<'a>;
+impl<'a, N: Number> CounterStorage<'a, N> for VecCounter<N> {
+    type ForWidth = VecCounterWidth<'a, N>;
 
-    fn new(digits: usize) -> Self {
+    fn new(digits: usize, radix: usize) -> Self {
         let mut count_maps = Vec::new();
         let mut vec_len = 1;
         for _number_width in 0..(digits + 1) {
             count_maps.push(vec![0; vec_len]);
-            vec_len *= 16;
+            vec_len *= radix;
+        }
+        Self {
+            count_maps,
+            digits,
+            _number: PhantomData,
+        }
+    }
+    fn merge(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for (v, other_v) in map.iter_mut().zip(other_map) {
+                *v += other_v;
+            }
+        }
+    }
+    fn merge_min(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for (v, other_v) in map.iter_mut().zip(other_map) {
+                if *v == 0 || (other_v != 0 && other_v < *v) {
+                    *v = other_v;
+                }
+            }
         }
-        Self { count_maps, digits }
     }
     fn width_and_prev_width(&'a mut self, width: usize) -> (Self::ForWidth, Self::ForWidth) {
         let (prev, current) = self.count_maps.split_at_mut(width);
@@ -146,93 +334,350 @@ impl<'a> CounterStorage<'a> for VecCounter {
         (
             VecCounterWidth {
                 digits: self.digits,
-                width: width,
+                width,
                 map: current,
+                _number: PhantomData,
             },
             VecCounterWidth {
                 digits: self.digits,
                 width: width - 1,
                 map: prev,
+                _number: PhantomData,
             },
         )
     }
 }
 
-pub struct VecCounterWidth<'a> {
+pub struct VecCounterWidth<'a, N> {
     map: &'a mut Vec<Counter>,
     digits: usize,
     width: usize,
+    _number: PhantomData<N>,
 }
 
-impl<'a> CounterForWidth<'a> for VecCounterWidth<'a> {
-    fn for_each(&self, mut f: impl FnMut(Number, Counter)) {
+impl<'a, N: Number> CounterForWidth<'a, N> for VecCounterWidth<'a, N> {
+    fn for_each(&self, mut f: impl FnMut(N, Counter)) {
         for (k, v) in self.map.iter().copied().enumerate() {
-            f(k as Number, v);
+            f(N::from_index(k), v);
         }
     }
-    fn count_number(&mut self, v: Number, delta: u64) {
-        self.map[v as usize] += delta;
+    fn count_number(&mut self, v: N, delta: u64) {
+        self.map[v.to_index()] += delta;
         print_count_number_single_masked(v, 1, self.width, self.digits);
     }
+    fn keep_min(&mut self, v: N, value: Counter) {
+        let entry = &mut self.map[v.to_index()];
+        if *entry == 0 || value < *entry {
+            *entry = value;
+        }
+    }
+    fn for_each_missing(&self, _last: N, mut f: impl FnMut(N) -> bool) {
+        for (k, &v) in self.map.iter().enumerate() {
+            if v == 0 && !f(N::from_index(k)) {
+                return;
+            }
+        }
+    }
 }
 
-pub struct Variant<T, U> {
-    count_maps: U,
+/// A slot of a [`CompactVecCounter`], narrower than a [`Counter`].
+pub trait Slot: 'static + Copy + Default + Eq + Send {
+    /// Marks a slot whose count is in the overflow map.
+    const SATURATED: Self;
+
+    fn to_counter(self) -> Counter;
+    /// `None` if `count` does not fit below [`Slot::SATURATED`].
+    fn from_counter(count: Counter) -> Option<Self>;
+}
+
+macro_rules! impl_slot {
+    ($t:ty) => {
+        impl Slot for $t {
+            const SATURATED: Self = <$t>::MAX;
+
+            fn to_counter(self) -> Counter {
+                self as Counter
+            }
+            fn from_counter(count: Counter) -> Option<Self> {
+                Some(count as Self)
+                    .filter(|&slot| slot as Counter == count && slot != Self::SATURATED)
+            }
+        }
+    };
+}
+
+impl_slot!(u16);
+impl_slot!(u32);
+
+/// A table of `S` slots, with the exact counts of saturated ones on the
+/// side.
+struct CompactTable<S> {
+    slots: Vec<S>,
+    overflow: FastHashMap<usize, Counter>,
+}
+
+impl<S: Slot> CompactTable<S> {
+    fn new(len: usize) -> Self {
+        Self {
+            slots: vec![S::default(); len],
+            overflow: FastHashMap::default(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Counter {
+        let slot = self.slots[index];
+        if slot == S::SATURATED {
+            self.overflow[&index]
+        } else {
+            slot.to_counter()
+        }
+    }
+
+    fn set(&mut self, index: usize, count: Counter) {
+        if self.slots[index] == S::SATURATED {
+            self.overflow.remove(&index);
+        }
+        self.slots[index] = match S::from_counter(count) {
+            Some(slot) => slot,
+            None => {
+                self.overflow.insert(index, count);
+                S::SATURATED
+            }
+        };
+    }
+
+    fn add(&mut self, index: usize, delta: Counter) {
+        let slot = &mut self.slots[index];
+        if *slot != S::SATURATED {
+            if let Some(sum) = S::from_counter(slot.to_counter() + delta) {
+                *slot = sum;
+                return;
+            }
+        }
+        self.set(index, self.get(index) + delta);
+    }
+}
+
+/// Like [`VecCounter`], but with a `u32` or `u16` slot per number instead
+/// of a [`Counter`]. Counts that do not fit are promoted to an overflow map,
+/// so they stay exact.
+pub struct CompactVecCounter<N = u64, S = u32> {
+    count_maps: Vec<CompactTable<S>>,
     digits: usize,
-    current_number: u64,
-    current_digits: usize,
-    _strat: T,
+    _number: PhantomData<N>,
 }
 
-impl<T: CountStrategy, U: for<'a> CounterStorage<'a>> Variant<T, U> {
-    fn new_internal(digits: usize) -> Self {
-        assert!(std::mem::size_of::<Number>() * 2 >= digits);
+impl<'a, N: Number, S: Slot> CounterStorage<'a, N> for CompactVecCounter<N, S> {
+    type ForWidth = CompactVecCounterWidth<'a, N, S>;
 
-        let count_maps = U::new(digits);
+    fn new(digits: usize, radix: usize) -> Self {
+        let mut count_maps = Vec::new();
+        let mut vec_len = 1;
+        for _number_width in 0..(digits + 1) {
+            count_maps.push(CompactTable::new(vec_len));
+            vec_len *= radix;
+        }
+        Self {
+            count_maps,
+            digits,
+            _number: PhantomData,
+        }
+    }
+    fn merge(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for index in 0..other_map.slots.len() {
+                let count = other_map.get(index);
+                if count != 0 {
+                    map.add(index, count);
+                }
+            }
+        }
+    }
+    fn merge_min(&mut self, other: Self) {
+        assert_eq!(self.digits, other.digits);
+        for (map, other_map) in self.count_maps.iter_mut().zip(other.count_maps) {
+            for index in 0..other_map.slots.len() {
+                let (v, other_v) = (map.get(index), other_map.get(index));
+                if other_v != 0 && (v == 0 || other_v < v) {
+                    map.set(index, other_v);
+                }
+            }
+        }
+    }
+    fn width_and_prev_width(&'a mut self, width: usize) -> (Self::ForWidth, Self::ForWidth) {
+        let (prev, current) = self.count_maps.split_at_mut(width);
+        let current = current.first_mut().unwrap();
+        let prev = prev.last_mut().unwrap();
+        (
+            CompactVecCounterWidth {
+                digits: self.digits,
+                width,
+                map: current,
+                _number: PhantomData,
+            },
+            CompactVecCounterWidth {
+                digits: self.digits,
+                width: width - 1,
+                map: prev,
+                _number: PhantomData,
+            },
+        )
+    }
+}
 
-        let mut masks = Vec::new();
+pub struct CompactVecCounterWidth<'a, N, S> {
+    map: &'a mut CompactTable<S>,
+    digits: usize,
+    width: usize,
+    _number: PhantomData<N>,
+}
 
-        for mask_width in 0..digits + 1 {
-            let mut mask: u64 = 0;
-            for _ in 0..mask_width {
-                mask <<= 4;
-                mask |= 0xf;
+impl<'a, N: Number, S: Slot> CounterForWidth<'a, N> for CompactVecCounterWidth<'a, N, S> {
+    fn for_each(&self, mut f: impl FnMut(N, Counter)) {
+        for k in 0..self.map.slots.len() {
+            f(N::from_index(k), self.map.get(k));
+        }
+    }
+    fn count_number(&mut self, v: N, delta: u64) {
+        self.map.add(v.to_index(), delta);
+        print_count_number_single_masked(v, 1, self.width, self.digits);
+    }
+    fn keep_min(&mut self, v: N, value: Counter) {
+        let index = v.to_index();
+        let entry = self.map.get(index);
+        if entry == 0 || value < entry {
+            self.map.set(index, value);
+        }
+    }
+    fn for_each_missing(&self, _last: N, mut f: impl FnMut(N) -> bool) {
+        for (k, &v) in self.map.slots.iter().enumerate() {
+            if v == S::default() && !f(N::from_index(k)) {
+                return;
             }
-            // println!("mask[{}] = {:x}", masks.len(), mask);
-            masks.push(mask);
         }
+    }
+}
+
+/// Counts digit strings of up to `N::max_digits` digits packed into `N`.
+///
+/// See `WideVariant` for longer strings.
+pub struct Variant<T, U, N = u64> {
+    count_maps: U,
+    /// Optionally the offset plus one of the first occurrence of every
+    /// number, in a table of the same shape as the counts.
+    first: Option<U>,
+    digits: usize,
+    alphabet: Alphabet,
+    classifier: Classifier,
+    radix: Radix<N>,
+    current_number: N,
+    current_digits: usize,
+    /// Offset of the next byte, the end of the register.
+    offset: u64,
+    _strat: T,
+}
+
+impl<T: CountStrategy, N: Number, U: for<'a> CounterStorage<'a, N>> Variant<T, U, N> {
+    fn new_internal(digits: usize, alphabet: &Alphabet) -> Self {
+        let max_digits = N::max_digits(alphabet.radix());
+        assert!(
+            max_digits >= digits,
+            "at most {} digits fit into the number type",
+            max_digits
+        );
 
-        let current_number: u64 = 0;
+        let count_maps = U::new(digits, alphabet.radix());
+
+        let radix = Radix::new(alphabet, digits);
+
+        let current_number = N::default();
         let current_digits = 0;
 
         Self {
             count_maps,
+            first: None,
             digits,
+            alphabet: alphabet.clone(),
+            classifier: Classifier::new(alphabet),
+            radix,
             current_number,
             current_digits,
+            offset: 0,
             _strat: T::default(),
         }
     }
 
+    /// The scalar reference for [`Variant::count_bytes`].
     fn count_digit(&mut self, byte: u8) {
-        let v = DIGIT_MAP[byte as usize];
+        let v = self.alphabet.digit_map()[byte as usize];
+
+        if v == NO_DIGIT {
+            self.end_run();
+        } else {
+            self.push_digit(v);
+        }
+    }
 
-        if v == 0xff {
-            if self.current_digits != 0 {
-                self.count_number_end(self.current_number, self.current_digits);
-                self.current_digits = 0;
+    /// Counts all bytes of `bytes`, like calling `on_byte` for each.
+    ///
+    /// Bytes are classified a block at a time with SIMD where available,
+    /// and the runs of digits between non-digits are pushed without
+    /// further checks.
+    pub fn count_bytes(&mut self, bytes: &[u8]) {
+        let mut values = [0; BLOCK];
+        let mut blocks = bytes.chunks_exact(BLOCK);
+        for block in &mut blocks {
+            let block = block.try_into().unwrap();
+            let mut mask = self.classifier.classify(block, &mut values);
+            let mut start = 0;
+            loop {
+                let end = if mask == 0 {
+                    BLOCK
+                } else {
+                    mask.trailing_zeros() as usize
+                };
+                for &v in &values[start..end] {
+                    self.push_digit(v);
+                }
+                if end == BLOCK {
+                    break;
+                }
+                self.end_run();
+                mask &= mask - 1;
+                start = end + 1;
             }
-            return;
         }
+        for &byte in blocks.remainder() {
+            self.count_digit(byte);
+        }
+    }
+
+    /// Counts the pending digit string at a non-digit.
+    #[inline]
+    fn end_run(&mut self) {
+        if self.current_digits != 0 {
+            self.count_number_end(self.current_number, self.current_digits);
+            self.current_digits = 0;
+            self.current_number = N::default();
+        }
+        self.offset += 1;
+    }
 
+    #[inline]
+    fn push_digit(&mut self, v: u8) {
         if self.current_digits == self.digits {
             self.count_number_mid(self.current_number, self.current_digits);
             self.current_digits -= 1;
+            // NB: Keeps the number in range for the next push.
+            self.current_number = self
+                .radix
+                .truncate(self.current_number, self.current_digits);
         }
 
-        self.current_number <<= 4;
-        self.current_number |= v as u64;
+        self.current_number = self.radix.push(self.current_number, v);
         self.current_digits += 1;
+        self.offset += 1;
     }
 
     fn count_digit_end(&mut self) {
@@ -241,25 +686,33 @@ impl<T: CountStrategy, U: for<'a> CounterStorage<'a>> Variant<T, U> {
         }
     }
 
-    fn count_number(&mut self, v: u64, width: usize) {
-        let mut v = v & HEX_MASKS[width];
+    /// Counts the last `width` digits of the register `v`, and with
+    /// `EarlyCount` their prefixes.
+    fn count_number(&mut self, v: N, width: usize) {
+        // NB: Wraps after resuming from a checkpoint, which does not keep
+        // the offset. First offsets are not recorded then.
+        let start = self.offset.wrapping_sub(width as u64);
+        let mut v = self.radix.truncate(v, width);
         for width in (1..width + 1).rev() {
             self.count_maps.width(width).count_number(v, 1);
-            v >>= 4;
+            if let Some(first) = &mut self.first {
+                first.width(width).keep_min(v, start + 1);
+            }
+            v = self.radix.prefix(v);
             if T::COUNT_LATE {
                 break;
             }
         }
     }
 
-    fn count_number_end(&mut self, v: u64, mut width: usize) {
+    fn count_number_end(&mut self, v: N, mut width: usize) {
         while width != 0 {
             self.count_number(v, width);
             width -= 1;
         }
     }
 
-    fn count_number_mid(&mut self, v: u64, width: usize) {
+    fn count_number_mid(&mut self, v: N, width: usize) {
         self.count_number(v, width);
     }
 
@@ -267,31 +720,209 @@ impl<T: CountStrategy, U: for<'a> CounterStorage<'a>> Variant<T, U> {
         if T::COUNT_LATE {
             //println!("Count all prefixes of numbers");
             for digits in (2..(self.digits + 1)).rev() {
+                let radix = &self.radix;
                 let (current, mut prev) = self.count_maps.width_and_prev_width(digits);
 
                 //println!("  Numbers with {} digits", digits);
                 current.for_each(|number, count| {
                     //println!("  prefix of {:0width$x}: {}", number, count, width = digits);
-                    let prefix_number = number >> 4;
+                    let prefix_number = radix.prefix(number);
                     prev.count_number(prefix_number, count);
                 });
             }
+            // NB: A prefix starts where the string does.
+            if let Some(first) = &mut self.first {
+                for digits in (2..(self.digits + 1)).rev() {
+                    let radix = &self.radix;
+                    let (current, mut prev) = first.width_and_prev_width(digits);
+                    current.for_each(|number, first| {
+                        if first != 0 {
+                            prev.keep_min(radix.prefix(number), first);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// Starts recording first offsets, see
+    /// [`Process::record_first_offsets`].
+    pub fn record_first_offsets(&mut self, start: u64) {
+        self.offset = start;
+        self.first = Some(U::new(self.digits, self.alphabet.radix()));
+    }
+
+    /// The recorded first offsets of the finalized counts, see
+    /// [`Process::first_offsets`].
+    pub fn first_offsets(&mut self) -> Option<FirstOffsets> {
+        let first = self.first.as_mut()?;
+        let mut map = FastHashMap::default();
+        for width in 1..self.digits + 1 {
+            let (radix, alphabet) = (&self.radix, &self.alphabet);
+            first.width(width).for_each(|number, first| {
+                if first != 0 {
+                    map.insert(radix.render(number, width, alphabet), first - 1);
+                }
+            });
+        }
+        Some(FirstOffsets::from_map(self.digits, map))
+    }
+
+    /// Adds the final counts of `other` to `self`.
+    ///
+    /// Both sides must already be finalized.
+    pub(crate) fn merge(&mut self, other: Self) {
+        self.count_maps.merge(other.count_maps);
+        if let (Some(first), Some(other)) = (&mut self.first, other.first) {
+            first.merge_min(other);
+        }
+    }
+
+    /// Counts every digit string in `bytes` that starts before `boundary`
+    /// and ends at or after it.
+    ///
+    /// These are exactly the strings missed when `bytes[..boundary]` and
+    /// `bytes[boundary..]` are counted separately. Counts are added to
+    /// all widths directly, so this must happen after finalizing. `offset`
+    /// is the offset of `bytes[0]`, for first offsets.
+    pub(crate) fn count_straddling(&mut self, bytes: &[u8], boundary: usize, offset: u64) {
+        let first_start = boundary.saturating_sub(self.digits.saturating_sub(1));
+        for start in first_start..boundary {
+            let mut number = N::default();
+            for (i, &byte) in bytes[start..].iter().take(self.digits).enumerate() {
+                let v = self.alphabet.digit_map()[byte as usize];
+                if v == NO_DIGIT {
+                    break;
+                }
+                number = self.radix.push(number, v);
+                let width = i + 1;
+                if start + width > boundary {
+                    self.count_maps.width(width).count_number(number, 1);
+                    if let Some(first) = &mut self.first {
+                        first
+                            .width(width)
+                            .keep_min(number, offset + start as u64 + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ranks the finalized counts of `width` digits, see [`Process::ranking`].
+    pub fn ranking(&mut self, width: usize, order: Order, k: usize) -> Ranking {
+        let count_maps = &mut self.count_maps;
+        let entries = ranking::select(k, order, |f| count_maps.width(width).for_each(f));
+        let entries = entries
+            .into_iter()
+            .map(|(number, count)| (self.radix.render(number, width, &self.alphabet), count));
+        Ranking::new(width, order, k, entries.collect())
+    }
+
+    /// Lists the finalized strings of `width` digits that never occurred,
+    /// see [`Process::missing`].
+    pub fn missing(&mut self, width: usize, limit: usize) -> Missing {
+        let (radix, alphabet) = (&self.radix, &self.alphabet);
+        let counter = self.count_maps.width(width);
+        let mut present = 0;
+        counter.for_each(|_, count| present += (count != 0) as u128);
+
+        let mut smallest = Vec::new();
+        counter.for_each_missing(radix.last(width), |number| {
+            smallest.push(radix.render(number, width, alphabet));
+            smallest.len() < limit.max(1)
+        });
+        Missing::new(width, alphabet.radix(), present, smallest)
+    }
+
+    /// Computes the statistics of the finalized counts of `width` digits,
+    /// see [`Process::stats`].
+    pub fn stats(&mut self, width: usize) -> Stats {
+        let mut builder = StatsBuilder::default();
+        self.count_maps
+            .width(width)
+            .for_each(|_, count| builder.add(count));
+        builder.finish(width, self.alphabet.radix())
+    }
+
+    /// Writes the register and all non-zero counts. Each width is a list
+    /// of `(count, number)` pairs ending with a zero count.
+    fn save_state_internal(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        write_u128(w, self.current_number.to_u128())?;
+        write_u64(w, self.current_digits as u64)?;
+        for width in 1..self.digits + 1 {
+            let mut result = Ok(());
+            self.count_maps.width(width).for_each(|number, count| {
+                if count != 0 && result.is_ok() {
+                    result = write_u64(w, count).and_then(|_| write_u128(w, number.to_u128()));
+                }
+            });
+            result?;
+            write_u64(w, 0)?;
+        }
+        Ok(())
+    }
+
+    fn load_state_internal(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let number = |r: &mut dyn Read| {
+            let v = read_u128(r)?;
+            N::try_from_u128(v).ok_or_else(|| {
+                checkpoint::invalid_data(format!("checkpoint number {} is too large", v))
+            })
+        };
+        let current_number = number(r)?;
+        let current_digits = read_u64(r)? as usize;
+        if current_digits > self.digits {
+            return Err(checkpoint::invalid_data(format!(
+                "checkpoint register has {} digits, at most {} are counted",
+                current_digits, self.digits
+            )));
+        }
+        if current_number > self.radix.last(current_digits) {
+            return Err(checkpoint::invalid_data(format!(
+                "checkpoint register {} has more than {} digits",
+                current_number.to_u128(),
+                current_digits
+            )));
+        }
+        self.current_number = current_number;
+        self.current_digits = current_digits;
+        for width in 1..self.digits + 1 {
+            let last = self.radix.last(width);
+            let mut counter = self.count_maps.width(width);
+            loop {
+                let count = read_u64(r)?;
+                if count == 0 {
+                    break;
+                }
+                let v = number(r)?;
+                if v > last {
+                    return Err(checkpoint::invalid_data(format!(
+                        "checkpoint number {} has more than {} digits",
+                        v.to_u128(),
+                        width
+                    )));
+                }
+                counter.count_number(v, count);
+            }
         }
+        Ok(())
     }
 
     fn _debug_output(&mut self) {
         for digits in (1..(self.digits + 1)).rev() {
             println!("Digit counts for width = {}", digits);
 
+            let (radix, alphabet) = (&self.radix, &self.alphabet);
             self.count_maps.width(digits).for_each(|number, count| {
-                println!("  {:0width$x}: {}", number, count, width = digits);
+                let string = radix.render(number, digits, alphabet);
+                println!("  {}: {}", String::from_utf8_lossy(&string), count);
             });
         }
     }
 }
 
 #[allow(unused_variables)]
-fn print_count_number_single_masked(v: u64, delta: Counter, width: usize, digits: usize) {
+fn print_count_number_single_masked<N: Number>(v: N, delta: Counter, width: usize, digits: usize) {
     /*
     println!(
         "  count {:0width$x}{:width2$}+{}",
@@ -304,36 +935,59 @@ fn print_count_number_single_masked(v: u64, delta: Counter, width: usize, digits
     */
 }
 
-impl<T: CountStrategy, U: for<'a> CounterStorage<'a>> Process for Variant<T, U> {
-    fn new(digits: usize) -> Self {
-        Self::new_internal(digits)
+impl<T: CountStrategy, N: Number, U: for<'a> CounterStorage<'a, N>> Process for Variant<T, U, N> {
+    fn new(digits: usize, alphabet: &Alphabet) -> Self {
+        Self::new_internal(digits, alphabet)
     }
     fn on_byte(&mut self, b: u8) {
         self.count_digit(b);
     }
+    fn on_chunk(&mut self, chunk: &[u8]) {
+        self.count_bytes(chunk);
+    }
     fn finalize(&mut self) {
         self.count_digit_end();
         self.do_late_counts();
     }
-    fn into_count(mut self) -> FastHashMap<Vec<u8>, Counter> {
+    fn into_count(mut self) -> Counts {
         let mut map = FastHashMap::default();
         let digits = self.digits;
 
         for digits in 1..digits + 1 {
-            self.count_maps.width(digits).for_each(|mut number, count| {
-                let mut vec = Vec::new();
-                for _ in 0..digits {
-                    let c = (number & 0xf) as u8;
-                    let c = if c < 10 { b'0' + c } else { b'a' + (c - 10) };
-                    vec.push(c);
-                    number >>= 4;
+            let (radix, alphabet) = (&self.radix, &self.alphabet);
+            self.count_maps.width(digits).for_each(|number, count| {
+                // NB: VecCounter also reports strings that never occurred.
+                if count == 0 {
+                    return;
                 }
-                vec.reverse();
+                let vec = radix.render(number, digits, alphabet);
                 assert!(map.insert(vec, count as Counter).is_none());
             });
         }
 
-        map
+        Counts::from_map(digits, map)
+    }
+    fn ranking(&mut self, width: usize, order: Order, k: usize) -> Option<Ranking> {
+        Some(Variant::ranking(self, width, order, k))
+    }
+    fn missing(&mut self, width: usize, limit: usize) -> Option<Missing> {
+        Some(Variant::missing(self, width, limit))
+    }
+    fn stats(&mut self, width: usize) -> Option<Stats> {
+        Some(Variant::stats(self, width))
+    }
+    fn record_first_offsets(&mut self, start: u64) -> bool {
+        Variant::record_first_offsets(self, start);
+        true
+    }
+    fn first_offsets(&mut self) -> Option<FirstOffsets> {
+        Variant::first_offsets(self)
+    }
+    fn save_state(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        self.save_state_internal(w)
+    }
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        self.load_state_internal(r)
     }
 }
 
@@ -342,38 +996,216 @@ mod tests {
     use super::*;
 
     fn test_out(d: usize, b: &[u8]) {
-        type Ctx = Variant<LateCount, HashMapCounter>;
-        let mut a = Ctx::new(d);
-        for b in b.iter().copied() {
-            a.on_byte(b);
+        fn check<T: Process>(d: usize, b: &[u8]) {
+            let alphabet = Alphabet::hex();
+            let mut a = T::new(d, &alphabet);
+            for b in b.iter().copied() {
+                a.on_byte(b);
+            }
+            a.finalize();
+            assert_eq!(a.into_count(), crate::reference::count(b, d, &alphabet));
         }
-        a.finalize();
-        a._debug_output();
+        check::<Variant<EarlyCount, HashMapCounter>>(d, b);
+        check::<Variant<LateCount, HashMapCounter>>(d, b);
+        check::<Variant<EarlyCount, VecCounter>>(d, b);
+        check::<Variant<LateCount, VecCounter>>(d, b);
+        check::<Variant<LateCount, CompactVecCounter>>(d, b);
+        check::<Variant<EarlyCount, CompactVecCounter<u64, u16>>>(d, b);
     }
 
     #[test]
     fn test_main() {
-        println!("-1----------------------------");
         test_out(2, b"1234567890");
-        println!("-2----------------------------");
         test_out(2, b"_1234_5678_90_7");
-        println!("-3----------------------------");
         test_out(5, b"1234567890_ffff");
-        println!("-4----------------------------");
         test_out(5, b"1_23_456_7890_abcde_f01234");
-        println!("-5----------------------------");
         test_out(5, b"1_23_456_7890_abcde_987654_f012341_23_456_123");
-        println!("-fin--------------------------");
     }
 
     #[test]
-    fn test_map() {
-        assert_eq!(DIGIT_MAP[b'a' as usize], 10);
-        assert_eq!(DIGIT_MAP[b'f' as usize], 15);
-        assert_eq!(DIGIT_MAP[b'A' as usize], 10);
-        assert_eq!(DIGIT_MAP[b'F' as usize], 15);
-        assert_eq!(DIGIT_MAP[b'0' as usize], 0);
-        assert_eq!(DIGIT_MAP[b'9' as usize], 9);
-        assert_eq!(DIGIT_MAP[b'_' as usize], 255);
+    fn test_compact_overflow() {
+        let alphabet = Alphabet::hex();
+        let mut input = b"12".repeat(40_000);
+        input.extend_from_slice(&b"7".repeat(70_000));
+        let mut expected = Variant::<LateCount, VecCounter>::new(3, &alphabet);
+        let mut imp = Variant::<LateCount, CompactVecCounter<u64, u16>>::new(3, &alphabet);
+        expected.count_bytes(&input);
+        imp.count_bytes(&input);
+        expected.finalize();
+        imp.finalize();
+        let counts = imp.into_count();
+        assert_eq!(counts.get(b"7"), 70_000);
+        assert_eq!(counts.get(b"12"), 40_000);
+        assert_eq!(counts, expected.into_count());
+
+        let mut table = CompactTable::<u16>::new(2);
+        table.add(1, 65_534);
+        assert_eq!(table.overflow.len(), 0);
+        table.add(1, 1);
+        assert_eq!((table.slots[1], table.get(1)), (u16::MAX, 65_535));
+        table.set(1, 3);
+        assert_eq!((table.slots[1], table.overflow.len()), (3, 0));
+    }
+
+    #[test]
+    fn test_max_digits() {
+        assert_eq!(u64::max_digits(16), 16);
+        assert_eq!(u64::max_digits(10), 19);
+        assert_eq!(u64::max_digits(2), 64);
+        assert_eq!(u64::max_digits(36), 12);
+        assert_eq!(u128::max_digits(16), 32);
+        assert_eq!(u128::max_digits(10), 38);
+    }
+
+    #[test]
+    fn test_decimal() {
+        let mut a = Variant::<LateCount, HashMapCounter>::new(19, &Alphabet::decimal());
+        for b in b"12a99999999999999999999_31".iter().copied() {
+            a.on_byte(b);
+        }
+        a.finalize();
+        let counts = a.into_count();
+        assert_eq!(counts.get(b"9999999999999999999"), 2);
+        assert_eq!(counts.get(b"999"), 18);
+        assert_eq!(counts.get(b"12"), 1);
+        assert_eq!(counts.get(b"31"), 1);
+        assert_eq!(counts.get(b"a"), 0);
+    }
+
+    #[test]
+    fn test_ranking() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        let mut a = Variant::<LateCount, VecCounter>::new(3, &Alphabet::hex());
+        for b in input.iter().copied() {
+            a.on_byte(b);
+        }
+        a.finalize();
+        let mut rankings = Vec::new();
+        for &order in &[Order::Top, Order::Bottom] {
+            for width in 1..4 {
+                for k in 0..4 {
+                    rankings.push(a.ranking(width, order, k));
+                }
+            }
+        }
+        let counts = a.into_count();
+        for ranking in rankings {
+            let expected = Ranking::from_counts(&counts, ranking.width, ranking.order, ranking.k);
+            assert_eq!(ranking, expected);
+        }
+    }
+
+    #[test]
+    fn test_missing() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        for &alphabet in &["hex", "decimal"] {
+            let alphabet: Alphabet = alphabet.parse().unwrap();
+            let mut a = Variant::<LateCount, VecCounter>::new(3, &alphabet);
+            let mut b = Variant::<EarlyCount, HashMapCounter<u128>, u128>::new(3, &alphabet);
+            for byte in input.iter().copied() {
+                a.on_byte(byte);
+                b.on_byte(byte);
+            }
+            a.finalize();
+            b.finalize();
+            let mut reports = Vec::new();
+            for width in 1..4 {
+                for &limit in &[0, 1, 5, 10000] {
+                    reports.push(a.missing(width, limit));
+                    assert_eq!(b.missing(width, limit), *reports.last().unwrap());
+                }
+            }
+            let counts = a.into_count();
+            for (i, report) in reports.into_iter().enumerate() {
+                let limit = [0, 1, 5, 10000][i % 4];
+                let expected = Missing::from_counts(&counts, &alphabet, report.width, limit);
+                assert_eq!(report, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        let alphabet = Alphabet::hex();
+        let mut a = Variant::<LateCount, VecCounter>::new(3, &alphabet);
+        let mut b = Variant::<EarlyCount, HashMapCounter<u128>, u128>::new(3, &alphabet);
+        for byte in input.iter().copied() {
+            a.on_byte(byte);
+            b.on_byte(byte);
+        }
+        a.finalize();
+        b.finalize();
+        let stats = (1..4).map(|width| a.stats(width)).collect::<Vec<_>>();
+        let counts = a.into_count();
+        for s in stats {
+            assert_eq!(b.stats(s.width), s);
+            assert_eq!(Stats::from_counts(&counts, 16, s.width), s);
+        }
+    }
+
+    fn check_first_offsets<T, U, N>(input: &[u8], digits: usize)
+    where
+        T: CountStrategy,
+        N: Number,
+        U: for<'a> CounterStorage<'a, N>,
+    {
+        let alphabet = Alphabet::hex();
+        let mut expected = FirstOffsets::new(digits);
+        for start in 0..input.len() {
+            let run = input[start..]
+                .iter()
+                .take(digits)
+                .take_while(|&&b| alphabet.digit(b).is_some())
+                .count();
+            for width in 1..run + 1 {
+                expected.insert(&input[start..start + width], 100 + start as u64);
+            }
+        }
+
+        let mut imp = Variant::<T, U, N>::new(digits, &alphabet);
+        imp.record_first_offsets(100);
+        imp.count_bytes(&input[..40]);
+        imp.count_bytes(&input[40..]);
+        imp.finalize();
+        assert_eq!(imp.first_offsets(), Some(expected), "digits={}", digits);
+    }
+
+    #[test]
+    fn test_first_offsets() {
+        let input =
+            b"1_23_456_7890_abcde_987654_f012341_23_456_123_0123456789abcdef01234_5".repeat(2);
+        for digits in 1..6 {
+            check_first_offsets::<EarlyCount, HashMapCounter, u64>(&input, digits);
+            check_first_offsets::<LateCount, HashMapCounter, u64>(&input, digits);
+            check_first_offsets::<EarlyCount, VecCounter, u64>(&input, digits);
+            check_first_offsets::<LateCount, VecCounter<u128>, u128>(&input, digits);
+        }
+        let mut imp = Variant::<LateCount, VecCounter>::new(2, &Alphabet::hex());
+        imp.finalize();
+        assert_eq!(imp.first_offsets(), None);
+    }
+
+    #[test]
+    fn test_count_bytes() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123_ABCDEF0123456789abcdef01234_5"
+            .repeat(3);
+        for &alphabet in &["hex", "decimal", "base36", "9876543210"] {
+            let alphabet: Alphabet = alphabet.parse().unwrap();
+            for digits in 1..6 {
+                for &skip in &[0, 1, 31, 33] {
+                    let mut expected = Variant::<LateCount, HashMapCounter>::new(digits, &alphabet);
+                    let mut imp = Variant::<LateCount, HashMapCounter>::new(digits, &alphabet);
+                    for &b in &input[skip..] {
+                        expected.on_byte(b);
+                    }
+                    imp.count_bytes(&input[skip..skip + 40]);
+                    imp.count_bytes(&input[skip + 40..]);
+                    expected.finalize();
+                    imp.finalize();
+                    assert_eq!(imp.into_count(), expected.into_count());
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,175 @@
+use crate::checkpoint::{self, read_u64, write_u64};
+use crate::{Alphabet, Counter, Counts, FastHashMap, Process, NO_DIGIT};
+use std::io::{self, Read, Write};
+
+/// Counts digit strings of any width, keyed by the strings themselves.
+///
+/// This is the fallback for widths that do not fit into a packed `Number`.
+/// It follows `Variant<LateCount, HashMapCounter>`: only the strings of the
+/// full width and the suffixes of each run are counted directly, shorter
+/// strings are derived from their longer extension when finalizing.
+pub struct WideVariant {
+    count_maps: Vec<FastHashMap<Vec<u8>, Counter>>,
+    digits: usize,
+    alphabet: Alphabet,
+    /// Holds the current run of digits, at most `digits` long, from
+    /// `start` on. Room for twice that keeps sliding the run along O(1) per
+    /// byte, as it is only moved back to the front when the buffer is full.
+    current: Vec<u8>,
+    start: usize,
+}
+
+impl WideVariant {
+    /// The current run of digits.
+    fn run(&self) -> &[u8] {
+        &self.current[self.start..]
+    }
+
+    /// Counts the current run from the buffer index `start` on.
+    fn count_string(&mut self, start: usize) {
+        let string = &self.current[start..];
+        let map = &mut self.count_maps[string.len()];
+        if let Some(count) = map.get_mut(string) {
+            *count += 1;
+        } else {
+            map.insert(string.to_vec(), 1);
+        }
+    }
+
+    fn count_digit(&mut self, byte: u8) {
+        let v = self.alphabet.digit_map()[byte as usize];
+
+        if v == NO_DIGIT {
+            self.end_of_run();
+            return;
+        }
+
+        if self.run().len() == self.digits {
+            self.count_string(self.start);
+            self.start += 1;
+        }
+        if self.current.len() == 2 * self.digits {
+            self.current.drain(..self.start);
+            self.start = 0;
+        }
+
+        self.current.push(self.alphabet.symbol(v));
+    }
+
+    fn end_of_run(&mut self) {
+        for start in self.start..self.current.len() {
+            self.count_string(start);
+        }
+        self.current.clear();
+        self.start = 0;
+    }
+
+    fn do_late_counts(&mut self) {
+        for digits in (2..(self.digits + 1)).rev() {
+            let (prev, current) = self.count_maps.split_at_mut(digits);
+            let prev = prev.last_mut().unwrap();
+            for (string, &count) in current[0].iter() {
+                *prev.entry(string[..digits - 1].to_vec()).or_default() += count;
+            }
+        }
+    }
+}
+
+impl Process for WideVariant {
+    fn new(digits: usize, alphabet: &Alphabet) -> Self {
+        Self {
+            count_maps: (0..digits + 1).map(|_| FastHashMap::default()).collect(),
+            digits,
+            alphabet: alphabet.clone(),
+            current: Vec::with_capacity(2 * digits),
+            start: 0,
+        }
+    }
+    fn on_byte(&mut self, b: u8) {
+        self.count_digit(b);
+    }
+    fn finalize(&mut self) {
+        self.end_of_run();
+        self.do_late_counts();
+    }
+    fn into_count(self) -> Counts {
+        let map = self.count_maps.into_iter().flatten().collect();
+        Counts::from_map(self.digits, map)
+    }
+    /// Same layout as for `Variant`, with the strings in place of numbers.
+    fn save_state(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        write_u64(w, self.run().len() as u64)?;
+        w.write_all(self.run())?;
+        for map in &self.count_maps {
+            for (string, &count) in map {
+                write_u64(w, count)?;
+                w.write_all(string)?;
+            }
+            write_u64(w, 0)?;
+        }
+        Ok(())
+    }
+    fn load_state(&mut self, r: &mut dyn Read) -> io::Result<()> {
+        let len = read_u64(r)? as usize;
+        if len > self.digits {
+            return Err(checkpoint::invalid_data(format!(
+                "checkpoint register has {} digits, at most {} are counted",
+                len, self.digits
+            )));
+        }
+        self.current.resize(len, 0);
+        self.start = 0;
+        r.read_exact(&mut self.current)?;
+        for (width, map) in self.count_maps.iter_mut().enumerate() {
+            loop {
+                let count = read_u64(r)?;
+                if count == 0 {
+                    break;
+                }
+                let mut string = vec![0; width];
+                r.read_exact(&mut string)?;
+                *map.entry(string).or_default() += count;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::{HashMapCounter, LateCount, Variant};
+
+    fn count<T: Process>(digits: usize, bytes: &[u8]) -> Counts {
+        let mut imp = T::new(digits, &Alphabet::hex());
+        for &b in bytes {
+            imp.on_byte(b);
+        }
+        imp.finalize();
+        imp.into_count()
+    }
+
+    #[test]
+    fn test_matches_variant() {
+        let input = b"1_23_456_7890_abcde_987654_f012341_23_456_123";
+        for digits in 1..8 {
+            assert_eq!(
+                count::<WideVariant>(digits, input),
+                count::<Variant<LateCount, HashMapCounter>>(digits, input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_strings() {
+        let input = b"x0123456789abcdef0123456789abcdef0123456789x";
+        let counts = count::<WideVariant>(40, input);
+        assert_eq!(counts.get(b"0123456789abcdef0123456789abcdef01234567"), 1);
+        assert_eq!(counts.get(b"0123456789abcdef0123456789abcdef0123456789"), 0);
+        assert_eq!(counts.get(b"0123456789abcdef"), 2);
+        assert_eq!(counts.width(40).len(), 3);
+
+        let variant = count::<Variant<LateCount, HashMapCounter<u128>, u128>>(32, input);
+        assert_eq!(count::<WideVariant>(32, input), variant);
+    }
+}
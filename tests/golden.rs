@@ -0,0 +1,161 @@
+//! Counts the small inputs in `tests/fixtures` with the binary, in every
+//! format, and compares all files it writes with those in `tests/golden`.
+//! Times are left out of the comparison.
+//!
+//! After an intended change of the output, rewrite the expected files with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+use count_digits::Format;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Fixture file names and the arguments to count them with.
+const FIXTURES: &[(&str, &[&str])] = &[
+    (
+        "pi.txt",
+        &[
+            "variant-4",
+            "2",
+            "--top",
+            "3",
+            "--missing",
+            "--stats",
+            "--first-offsets",
+            "--counts",
+        ],
+    ),
+    (
+        "noise.txt",
+        &[
+            "variant-2",
+            "3",
+            "--alphabet",
+            "decimal",
+            "--bottom",
+            "2",
+            "--list-missing",
+            "3",
+        ],
+    ),
+];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Replaces the number after each `elapsed_secs` key with 0.
+fn mask_elapsed(text: &str) -> String {
+    let mut masked = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find("elapsed_secs") {
+        let after_key = &rest[i + "elapsed_secs".len()..];
+        let number_start = after_key
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(after_key.len());
+        let number = &after_key[number_start..];
+        let number_len = number
+            .find(|c: char| !(c.is_ascii_digit() || ".e-".contains(c)))
+            .unwrap_or(number.len());
+        masked.push_str(&rest[..i + "elapsed_secs".len() + number_start]);
+        masked.push('0');
+        rest = &number[number_len..];
+    }
+    masked.push_str(rest);
+    masked
+}
+
+/// Runs the count and returns the written files by name, with masked times.
+fn run(fixture: &str, args: &[&str], format: Format) -> Vec<(String, String)> {
+    let dir = std::env::temp_dir().join(format!(
+        "count-digits-golden-{}-{}-{}",
+        std::process::id(),
+        fixture,
+        format
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(
+        tests_dir().join("fixtures").join(fixture),
+        dir.join(fixture),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_count-digits"))
+        .current_dir(&dir)
+        .arg(args[0])
+        .arg(fixture)
+        .args(&args[1..])
+        .args(["--format", format.name()])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "counting {} failed: {}",
+        fixture,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut files = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name != fixture)
+        .map(|name| {
+            let text = fs::read_to_string(dir.join(&name)).unwrap();
+            (name, mask_elapsed(&text))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    fs::remove_dir_all(&dir).unwrap();
+    files
+}
+
+#[test]
+fn test_golden_files() {
+    let golden = tests_dir().join("golden");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    for &(fixture, args) in FIXTURES {
+        let mut written = Vec::new();
+        for &format in Format::ALL {
+            let files = run(fixture, args, format);
+            assert!(!files.is_empty());
+            for (name, text) in files {
+                written.push(name.clone());
+                let path = golden.join(&name);
+                if update {
+                    fs::write(&path, &text).unwrap();
+                    continue;
+                }
+                let expected = fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+                assert!(
+                    text == expected,
+                    "{} differs from {}:\n{}",
+                    name,
+                    path.display(),
+                    text
+                );
+            }
+        }
+
+        // NB: Catches outputs that are no longer written.
+        let prefix = format!("{}_", fixture.trim_end_matches(".txt"));
+        let mut expected = fs::read_dir(&golden)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        expected.sort();
+        written.sort();
+        written.dedup();
+        assert_eq!(written, expected, "files written for {}", fixture);
+    }
+}
+
+#[test]
+fn test_mask_elapsed() {
+    assert_eq!(
+        mask_elapsed("{\"elapsed_secs\":1.5e-5,\"x\":1}\n# elapsed_secs: 0.25\n"),
+        "{\"elapsed_secs\":0,\"x\":1}\n# elapsed_secs: 0\n"
+    );
+}
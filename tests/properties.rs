@@ -0,0 +1,151 @@
+//! Every engine must count random inputs exactly like the naive reference
+//! counter, however the input is split into chunks.
+
+use count_digits::generate::Rng;
+use count_digits::original::{AlphabetDigit, Original, StdNumeric};
+use count_digits::variant::{
+    CompactVecCounter, EarlyCount, HashMapCounter, LateCount, Variant, VecCounter,
+};
+use count_digits::wide::WideVariant;
+use count_digits::{reference, Alphabet, Counts, Process};
+
+const CASES: u64 = 300;
+
+/// A random input along with how to feed it.
+struct Case {
+    bytes: Vec<u8>,
+    digits: usize,
+    alphabet: Alphabet,
+    /// Lengths of the chunks passed to `on_chunk`, or empty to pass every
+    /// byte to `on_byte`.
+    chunks: Vec<usize>,
+}
+
+impl Case {
+    fn random(rng: &mut Rng) -> Self {
+        let alphabets = ["hex", "decimal", "binary", "ACGT"];
+        let alphabet: Alphabet = alphabets[rng.below(4) as usize].parse().unwrap();
+        let symbols = alphabet.symbols().to_vec();
+        let noise = b"_.\n x";
+        // NB: Mostly digits, so runs are often longer than the width.
+        let noise_probability = [0.0, 0.05, 0.3][rng.below(3) as usize];
+
+        let len = rng.below(400) as usize;
+        let bytes = (0..len)
+            .map(|_| {
+                if rng.next_f64() < noise_probability {
+                    noise[rng.below(noise.len() as u64) as usize]
+                } else if rng.below(50) == 0 {
+                    rng.below(256) as u8
+                } else {
+                    let symbol = symbols[rng.below(symbols.len() as u64) as usize];
+                    if rng.below(4) == 0 {
+                        symbol.to_ascii_uppercase()
+                    } else {
+                        symbol
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut chunks = Vec::new();
+        if rng.below(2) == 0 {
+            let mut left = len;
+            while left != 0 {
+                let chunk = 1 + rng.below(left.min(70) as u64) as usize;
+                chunks.push(chunk);
+                left -= chunk;
+            }
+        }
+        Self {
+            bytes,
+            digits: 1 + rng.below(6) as usize,
+            alphabet,
+            chunks,
+        }
+    }
+
+    fn count<T: Process>(&self, digits: usize) -> Counts {
+        let mut imp = T::new(digits, &self.alphabet);
+        if self.chunks.is_empty() {
+            for &b in &self.bytes {
+                imp.on_byte(b);
+            }
+        } else {
+            let mut rest = &self.bytes[..];
+            for &len in &self.chunks {
+                let (chunk, tail) = rest.split_at(len);
+                imp.on_chunk(chunk);
+                rest = tail;
+            }
+        }
+        imp.finalize();
+        imp.into_count()
+    }
+
+    fn check<T: Process>(&self, name: &str, digits: usize) {
+        let expected = reference::count(&self.bytes, digits, &self.alphabet);
+        let counts = self.count::<T>(digits);
+        assert!(
+            counts == expected,
+            "{} with {} digits of {:?} differs on {:?}: {:?}",
+            name,
+            digits,
+            String::from_utf8_lossy(self.alphabet.symbols()),
+            String::from_utf8_lossy(&self.bytes),
+            expected.differences(&counts).first()
+        );
+    }
+}
+
+#[test]
+fn test_variants() {
+    let mut rng = Rng::new(1);
+    for _ in 0..CASES {
+        let case = Case::random(&mut rng);
+        let d = case.digits;
+        case.check::<Variant<EarlyCount, HashMapCounter>>("variant-1", d);
+        case.check::<Variant<LateCount, HashMapCounter>>("variant-2", d);
+        // NB: Tables have an entry for every string, keep them small.
+        case.check::<Variant<EarlyCount, VecCounter>>("variant-3", d.min(4));
+        case.check::<Variant<LateCount, VecCounter>>("variant-4", d.min(4));
+        case.check::<Variant<LateCount, CompactVecCounter<u64, u16>>>("variant-4-u16", d.min(4));
+        case.check::<Variant<EarlyCount, HashMapCounter<u128>, u128>>("variant-1 u128", d);
+        case.check::<Variant<LateCount, VecCounter<u128>, u128>>("variant-4 u128", d.min(4));
+    }
+}
+
+#[test]
+fn test_wide_widths() {
+    let mut rng = Rng::new(2);
+    // NB: Fewer cases, as the reference gets slow for wide strings.
+    for _ in 0..CASES / 3 {
+        let case = Case::random(&mut rng);
+        // NB: Up to the widest strings a u128 holds and beyond.
+        let d = 10 + case.digits * 7;
+        case.check::<Variant<EarlyCount, HashMapCounter<u128>, u128>>("variant-1 u128", d.min(32));
+        case.check::<Variant<LateCount, HashMapCounter<u128>, u128>>("variant-2 u128", d.min(32));
+        case.check::<WideVariant>("wide", d);
+    }
+}
+
+#[test]
+fn test_original() {
+    let mut rng = Rng::new(3);
+    for _ in 0..CASES {
+        let case = Case::random(&mut rng);
+        case.check::<Original<AlphabetDigit>>("original-hex", case.digits);
+        if case.alphabet.symbols() == Alphabet::decimal().symbols() {
+            // NB: `char::is_numeric` also accepts non-ASCII bytes like `¾`.
+            let bytes = case
+                .bytes
+                .iter()
+                .map(|&b| if b.is_ascii() { b } else { b'_' });
+            let ascii = Case {
+                bytes: bytes.collect(),
+                ..case
+            };
+            ascii.check::<Original<StdNumeric>>("original", ascii.digits);
+        }
+    }
+}